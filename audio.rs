@@ -0,0 +1,236 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, anyhow};
+
+use crate::config;
+use crate::wav;
+
+/// Fills one block of stereo output at the given sample rate.
+pub type Callback = Box<dyn FnMut(u32, &mut [f32], &mut [f32]) + Send>;
+
+/// Something that periodically calls a `Callback` to produce audio.
+/// The stream runs from `start` until the backend is dropped.
+pub trait AudioBackend {
+    fn start(&mut self, process: Callback) -> Result<()>;
+}
+
+/// Opens the backend named by `config.backend`: `jack`, `null` or `file:<path.wav>`.
+pub fn open(config: &config::Audio) -> Result<Box<dyn AudioBackend>> {
+    let spec = config.backend.as_str();
+    Ok(match spec.split_once(':') {
+        None if spec == "jack" => Box::new(Jack::new(config.left.clone(), config.right.clone())?),
+        None if spec == "null" => Box::new(Null::new(config.rate, config.period)),
+        Some(("file", path)) => Box::new(File::new(path, config.rate, config.period)),
+        _ => anyhow::bail!("unknown audio backend: {spec} (expected jack, null or file:<path>)"),
+    })
+}
+
+
+pub struct Jack {
+    client: Option<jack::Client>,
+    active: Option<jack::AsyncClient<Notifications, JackProcess>>,
+    left: Vec<String>,
+    right: Vec<String>,
+}
+
+impl Jack {
+    /// `left` and `right` are the ports to connect each output to once started.
+    pub fn new(left: Vec<String>, right: Vec<String>) -> Result<Self> {
+        let (client, _) = jack::Client::new("synth", jack::ClientOptions::NO_START_SERVER)?;
+        Ok(Self { client: Some(client), active: None, left, right })
+    }
+
+    /// Names of all JACK audio ports, as (outputs, inputs).
+    pub fn list() -> Result<(Vec<String>, Vec<String>)> {
+        let (client, _) = jack::Client::new("synth_list", jack::ClientOptions::NO_START_SERVER)?;
+        let outputs = client.ports(None, Some(jack::jack_sys::FLOAT_MONO_AUDIO), jack::PortFlags::IS_OUTPUT);
+        let inputs = client.ports(None, Some(jack::jack_sys::FLOAT_MONO_AUDIO), jack::PortFlags::IS_INPUT);
+        Ok((outputs, inputs))
+    }
+
+    fn connect(client: &jack::Client, from: &str, to: &str) -> Result<()> {
+        if client.connect_ports_by_name(from, to).is_ok() {
+            return Ok(());
+        }
+
+        let available = client.ports(None, Some(jack::jack_sys::FLOAT_MONO_AUDIO), jack::PortFlags::IS_INPUT);
+        let mut msg = format!("failed to connect {from} to JACK port '{to}'\navailable ports:");
+        for port in &available {
+            msg += &format!("\n  '{port}'");
+        }
+        if available.is_empty() {
+            msg += " none";
+        }
+        anyhow::bail!(msg)
+    }
+}
+
+impl AudioBackend for Jack {
+    fn start(&mut self, process: Callback) -> Result<()> {
+        let client = self.client.take().ok_or_else(|| anyhow!("JACK backend already started"))?;
+
+        let rate = Arc::new(AtomicU32::new(client.sample_rate() as u32));
+        println!("JACK: rate={}", client.sample_rate());
+
+        let process = JackProcess {
+            out_left: client.register_port("out_left", jack::AudioOut)?,
+            out_right: client.register_port("out_right", jack::AudioOut)?,
+            rate: Arc::clone(&rate),
+            process,
+        };
+        let active = client.activate_async(Notifications { rate }, process)?;
+
+        let client = active.as_client();
+        for port in &self.left {
+            Self::connect(client, "synth:out_left", port)?;
+        }
+        for port in &self.right {
+            Self::connect(client, "synth:out_right", port)?;
+        }
+
+        self.active = Some(active);
+        Ok(())
+    }
+}
+
+struct JackProcess {
+    out_left: jack::Port<jack::AudioOut>,
+    out_right: jack::Port<jack::AudioOut>,
+    rate: Arc<AtomicU32>,
+    process: Callback,
+}
+
+impl jack::ProcessHandler for JackProcess {
+    fn process(&mut self, _: &jack::Client, ps: &jack::ProcessScope) -> jack::Control {
+        let left = self.out_left.as_mut_slice(ps);
+        let right = self.out_right.as_mut_slice(ps);
+        let rate = self.rate.load(Ordering::Relaxed);
+        (self.process)(rate, left, right);
+        jack::Control::Continue
+    }
+}
+
+struct Notifications {
+    rate: Arc<AtomicU32>,
+}
+
+impl jack::NotificationHandler for Notifications {
+    fn shutdown(&mut self, status: jack::ClientStatus, reason: &str) {}
+
+    fn sample_rate(&mut self, _: &jack::Client, srate: jack::Frames) -> jack::Control {
+        println!("JACK: rate={}", srate);
+        self.rate.store(srate, Ordering::Relaxed);
+        jack::Control::Continue
+    }
+
+    fn xrun(&mut self, _: &jack::Client) -> jack::Control {
+        println!("JACK: xrun");
+        jack::Control::Continue
+    }
+}
+
+
+/// Runs the callback on a timer thread at the given rate, passing each
+/// block to `sink` instead of a sound card.
+struct Timer {
+    rate: u32,
+    period: usize,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Timer {
+    fn new(rate: u32, period: usize) -> Self {
+        Self { rate, period, stop: Arc::new(AtomicBool::new(false)), thread: None }
+    }
+
+    fn start(&mut self, mut process: Callback, mut sink: impl FnMut(&[f32], &[f32]) + Send + 'static) {
+        let (rate, period) = (self.rate, self.period);
+        let stop = Arc::clone(&self.stop);
+
+        self.thread = Some(std::thread::spawn(move || {
+            let mut left = vec![0.0; period];
+            let mut right = vec![0.0; period];
+
+            let interval = Duration::from_secs_f64(period as f64 / rate as f64);
+            let mut next = Instant::now();
+
+            while !stop.load(Ordering::Relaxed) {
+                process(rate, &mut left, &mut right);
+                sink(&left, &right);
+
+                next += interval;
+                std::thread::sleep(next.saturating_duration_since(Instant::now()));
+            }
+        }));
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Runs the callback in real time and discards the output.
+pub struct Null(Timer);
+
+impl Null {
+    pub fn new(rate: u32, period: usize) -> Self {
+        Self(Timer::new(rate, period))
+    }
+}
+
+impl AudioBackend for Null {
+    fn start(&mut self, process: Callback) -> Result<()> {
+        self.0.start(process, |_, _| {});
+        Ok(())
+    }
+}
+
+/// Runs the callback in real time and writes the output to a WAV file.
+pub struct File {
+    timer: Timer,
+    path: PathBuf,
+}
+
+impl File {
+    pub fn new(path: impl Into<PathBuf>, rate: u32, period: usize) -> Self {
+        Self { timer: Timer::new(rate, period), path: path.into() }
+    }
+}
+
+impl AudioBackend for File {
+    fn start(&mut self, process: Callback) -> Result<()> {
+        let writer = wav::Writer::create(&self.path, self.timer.rate, wav::Format::Float32)?;
+        let mut sink = FileSink(Some(writer));
+
+        self.timer.start(process, move |left, right| {
+            let Some(w) = sink.0.as_mut() else { return };
+            let res = left.iter().zip(right).try_for_each(|(&l, &r)| w.write(l, r));
+            if let Err(e) = res {
+                println!("failed to write audio: {e}");
+                sink.0 = None;
+            }
+        });
+        Ok(())
+    }
+}
+
+/// Finalizes the WAV header once the timer thread exits.
+struct FileSink(Option<wav::Writer<std::io::BufWriter<std::fs::File>>>);
+
+impl Drop for FileSink {
+    fn drop(&mut self) {
+        if let Some(Err(e)) = self.0.take().map(|w| w.finish()) {
+            println!("failed to finish audio file: {e}");
+        }
+    }
+}
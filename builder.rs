@@ -0,0 +1,133 @@
+use std::process::Command;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, anyhow};
+use types::launchpad_x as lpx;
+
+use crate::config;
+use crate::reactor::Waker;
+use crate::watch::SourceWatcher;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Status {
+    Idle,
+    Building,
+    Failed,
+    Succeeded,
+}
+
+impl Status {
+    /// Launchpad colour for this status, in the device's channel order.
+    pub fn color(self) -> lpx::Rgb {
+        match self {
+            Status::Idle => lpx::Rgb(0.0, 0.0, 0.0),
+            Status::Building => lpx::Rgb(0.0, 1.0, 1.0),
+            Status::Failed => lpx::Rgb(0.0, 1.0, 0.0),
+            Status::Succeeded => lpx::Rgb(0.0, 0.0, 1.0),
+        }
+    }
+}
+
+/// Rebuilds the program whenever its sources change.
+///
+/// The build runs on its own thread with its output going straight to the
+/// console, so compiler errors show up as they would in a terminal. Failing to
+/// run it, or losing the watcher, is reported like a failed build.
+pub struct Builder {
+    /// `None` once it stopped, after which nothing is rebuilt.
+    watcher: Option<SourceWatcher>,
+    command: Vec<String>,
+    running: Option<Receiver<bool>>,
+    /// Sources changed while a build was running.
+    dirty: bool,
+    status: Status,
+    waker: Waker,
+}
+
+impl Builder {
+    pub fn new(config: &config::Build, debounce: Duration, waker: Waker) -> Result<Self> {
+        if config.command.is_empty() {
+            anyhow::bail!("build command is empty");
+        }
+
+        Ok(Self {
+            watcher: Some(SourceWatcher::new(&config.sources, debounce, waker.clone())?),
+            command: config.command.clone(),
+            running: None,
+            dirty: false,
+            status: Status::Idle,
+            waker,
+        })
+    }
+
+    pub fn status(&self) -> Status {
+        self.status
+    }
+
+    /// When pending source changes will have settled, to wake up for.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.watcher.as_ref().and_then(SourceWatcher::deadline)
+    }
+
+    /// Whether a build just finished successfully and the library should be reloaded.
+    pub fn poll(&mut self) -> bool {
+        if let Some(watcher) = &mut self.watcher {
+            match watcher.poll() {
+                Ok(changed) => self.dirty |= changed,
+                Err(e) => {
+                    println!("{e}, sources are no longer rebuilt");
+                    self.watcher = None;
+                    self.status = Status::Failed;
+                },
+            }
+        }
+
+        let mut succeeded = false;
+        if let Some(running) = &self.running {
+            match running.try_recv() {
+                Ok(ok) => {
+                    self.running = None;
+                    self.status = if ok { Status::Succeeded } else { Status::Failed };
+                    println!("build {}", if ok { "succeeded" } else { "failed" });
+                    succeeded = ok;
+                },
+                Err(TryRecvError::Empty) => {},
+                Err(TryRecvError::Disconnected) => {
+                    self.running = None;
+                    self.status = Status::Failed;
+                },
+            }
+        }
+
+        if self.dirty && self.running.is_none() {
+            self.dirty = false;
+            if let Err(e) = self.start() {
+                println!("build failed: {e}");
+                self.status = Status::Failed;
+            }
+        }
+
+        succeeded
+    }
+
+    fn start(&mut self) -> Result<()> {
+        println!("building: {}", self.command.join(" "));
+        let mut command = Command::new(&self.command[0]);
+        command.args(&self.command[1..]);
+        let mut child = command.spawn()
+            .map_err(|e| anyhow!("failed to run {}: {e}", self.command[0]))?;
+
+        let (tx, rx) = mpsc::channel();
+        let waker = self.waker.clone();
+        std::thread::spawn(move || {
+            let ok = child.wait().is_ok_and(|s| s.success());
+            let _ = tx.send(ok);
+            waker.wake();
+        });
+
+        self.running = Some(rx);
+        self.status = Status::Building;
+        Ok(())
+    }
+}
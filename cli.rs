@@ -0,0 +1,154 @@
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
+use types::ffi::Encoding;
+
+use crate::config::Config;
+use crate::wav;
+
+#[derive(Parser)]
+#[command(name = "synth", about = "Live host for the hot-reloadable program library")]
+pub struct Cli {
+    /// Config file, defaults to ./synth.toml if present.
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run the program live (the default).
+    Run(Run),
+    /// List MIDI and JACK ports.
+    Ports,
+    /// Render the program offline to a WAV file.
+    Render(Render),
+    /// Compare two WAV files, e.g. renders of the same session with different builds.
+    Diff {
+        a: PathBuf,
+        b: PathBuf,
+    },
+    /// Inspect saved program state.
+    #[command(subcommand)]
+    State(State),
+}
+
+#[derive(Args, Default)]
+pub struct Run {
+    /// Audio backend: jack, null or file:<path.wav>.
+    #[arg(long)]
+    pub backend: Option<String>,
+    /// Program library to load.
+    #[arg(long)]
+    pub lib: Option<PathBuf>,
+    /// Directory to watch for a rebuilt library.
+    #[arg(long)]
+    pub watch: Option<PathBuf>,
+    /// Start from a saved state snapshot instead of the default.
+    #[arg(long)]
+    pub state: Option<PathBuf>,
+    /// Rebuild the program when its sources change.
+    #[arg(long)]
+    pub build: bool,
+    /// Don't open any MIDI controllers.
+    #[arg(long)]
+    pub no_midi: bool,
+    /// Log every input sent to the program to a file, in the render script format,
+    /// or just the piano as a Standard MIDI File if it ends in `.mid`.
+    #[arg(long)]
+    pub log: Option<PathBuf>,
+    /// Play back a logged session, render script or MIDI file with its original timing.
+    #[arg(long)]
+    pub replay: Option<PathBuf>,
+}
+
+impl Run {
+    /// Applies the options that override the config.
+    pub fn apply(&mut self, config: &mut Config) {
+        if let Some(backend) = self.backend.take() {
+            config.audio.backend = backend;
+        }
+        if let Some(lib) = self.lib.take() {
+            config.library = lib;
+        }
+        if let Some(watch) = self.watch.take() {
+            config.watch = watch;
+        }
+        if self.build {
+            config.build.enabled = true;
+        }
+        if self.no_midi {
+            config.midi.enable_launch_control_xl = false;
+            config.midi.enable_launchpad_x = false;
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct Render {
+    /// Output WAV file.
+    pub out: PathBuf,
+    /// Script of timestamped inputs to feed the program, or a MIDI file to play on the piano.
+    #[arg(long)]
+    pub script: Option<PathBuf>,
+    /// Start from a saved state snapshot instead of the default.
+    #[arg(long)]
+    pub state: Option<PathBuf>,
+    #[arg(long, default_value_t = 48_000)]
+    pub rate: u32,
+    /// Length in seconds.
+    #[arg(long, default_value_t = 10.0)]
+    pub duration: f64,
+    /// Sample format: i16, i24 or f32.
+    #[arg(long, default_value = "i24")]
+    pub format: wav::Format,
+    /// Program library to load.
+    #[arg(long)]
+    pub lib: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+pub enum State {
+    /// Print a state snapshot as re-serialized by the library, or its default state.
+    Dump {
+        /// Snapshot to load, defaults to the library's default state.
+        snapshot: Option<PathBuf>,
+        /// Write to a file instead of stdout.
+        #[arg(long, short)]
+        out: Option<PathBuf>,
+        /// json or binary. Binary has to be written to a file with `--out`.
+        #[arg(long, default_value = "json")]
+        format: Encoding,
+        #[arg(long)]
+        lib: Option<PathBuf>,
+    },
+    /// List autosaved snapshots, oldest first.
+    List,
+    /// Check that the library can load a state snapshot.
+    Load {
+        snapshot: PathBuf,
+        #[arg(long)]
+        lib: Option<PathBuf>,
+    },
+    /// Check that the library can load every state fixture, e.g. one per schema version.
+    Check {
+        #[arg(default_value = "program/fixtures")]
+        dir: PathBuf,
+        #[arg(long)]
+        lib: Option<PathBuf>,
+    },
+    /// Time serializing and reloading a large wavetable state in each format.
+    Bench {
+        /// Wavetable rows and samples per row.
+        #[arg(long, default_value_t = 128)]
+        rows: usize,
+        #[arg(long, default_value_t = 10240)]
+        len: usize,
+        #[arg(long, default_value_t = 3)]
+        runs: u32,
+        #[arg(long)]
+        lib: Option<PathBuf>,
+    },
+}
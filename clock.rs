@@ -0,0 +1,30 @@
+use std::time::{Duration, Instant};
+
+/// Tempo from the intervals between taps of a button.
+#[derive(Default)]
+pub struct TapTempo {
+    taps: Vec<Instant>,
+}
+
+impl TapTempo {
+    /// Taps averaged over.
+    const TAPS: usize = 8;
+    /// A pause this long starts over.
+    const TIMEOUT: Duration = Duration::from_secs(2);
+
+    /// Registers a tap, returning the new tempo in BPM once there are at least two.
+    pub fn tap(&mut self, at: Instant) -> Option<f64> {
+        if self.taps.last().is_some_and(|&last| at.saturating_duration_since(last) > Self::TIMEOUT) {
+            self.taps.clear();
+        }
+        self.taps.push(at);
+        if self.taps.len() > Self::TAPS {
+            self.taps.remove(0);
+        }
+
+        let (first, last) = (self.taps.first()?, self.taps.last()?);
+        let beats = self.taps.len() - 1;
+        let beat = last.saturating_duration_since(*first).as_secs_f64() / beats as f64;
+        (beat > 0.0).then(|| (60.0 / beat).clamp(20.0, 300.0))
+    }
+}
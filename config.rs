@@ -0,0 +1,238 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use types::ffi::Encoding;
+
+use crate::wav;
+
+/// Host configuration, read from `synth.toml` in the working directory by default.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Program library to load.
+    pub library: PathBuf,
+    /// Directory to watch for a rebuilt library.
+    pub watch: PathBuf,
+    /// LED frames sent to the controllers per second.
+    pub frame_rate: u32,
+    /// Milliseconds the library has to stay unchanged before it's reloaded.
+    pub debounce: u64,
+    /// Milliseconds to crossfade between the old and new program on reload.
+    pub crossfade: u64,
+
+    pub midi: Midi,
+    pub audio: Audio,
+    pub state: State,
+    pub build: Build,
+    pub clock: Clock,
+    pub record: Record,
+}
+
+/// MIDI port names, matched by prefix.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Midi {
+    pub launch_control_xl: String,
+    pub launchpad_x: String,
+    /// Disabled surfaces are never opened, and the program runs as if they were unplugged.
+    pub enable_launch_control_xl: bool,
+    pub enable_launchpad_x: bool,
+    /// Octave of the program's piano layout, for MIDI file import and export.
+    pub piano_octave: i8,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Audio {
+    /// `jack`, `null` or `file:<path.wav>`.
+    pub backend: String,
+    /// JACK ports to connect `synth:out_left` and `synth:out_right` to.
+    pub left: Vec<String>,
+    pub right: Vec<String>,
+    /// Sample rate and block size for the `null` and `file` backends.
+    pub rate: u32,
+    pub period: usize,
+}
+
+/// Autosaved program state.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct State {
+    /// Directory for state snapshots.
+    pub dir: PathBuf,
+    /// Seconds between autosaves.
+    pub interval: u64,
+    /// Number of snapshots to keep.
+    pub keep: usize,
+    /// Restore the latest snapshot on startup.
+    pub restore: bool,
+    /// `json` or `binary`, for snapshots and for carrying state over on reload.
+    /// Binary is much faster for large states.
+    pub format: String,
+}
+
+/// Host-driven rebuilds of the program library.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Build {
+    /// Watch `sources` and run `command` on changes, reloading only after a successful build.
+    pub enabled: bool,
+    pub sources: PathBuf,
+    pub command: Vec<String>,
+}
+
+/// Musical time passed to the program alongside the frame count.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Clock {
+    /// Initial tempo in BPM.
+    pub tempo: f64,
+    pub beats_per_bar: u32,
+    /// Input that taps the tempo instead of reaching the program, in the
+    /// render script format (e.g. `ctrl select 3 on`), or empty for none.
+    pub tap: String,
+}
+
+/// Recording the live output.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Record {
+    /// Directory for recordings, named by the time they were started.
+    pub dir: PathBuf,
+    /// Sample format: i16, i24 or f32.
+    pub format: String,
+    /// Input that starts and stops recording, like `clock.tap`.
+    pub toggle: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            library: "target/release/libprogram.so".into(),
+            watch: "target/release".into(),
+            frame_rate: 60,
+            debounce: 250,
+            crossfade: 50,
+            midi: Midi::default(),
+            audio: Audio::default(),
+            state: State::default(),
+            build: Build::default(),
+            clock: Clock::default(),
+            record: Record::default(),
+        }
+    }
+}
+
+impl Default for Midi {
+    fn default() -> Self {
+        Self {
+            launch_control_xl: "Launch Control XL:Launch Control XL".into(),
+            launchpad_x: "Launchpad X:Launchpad X LPX MIDI".into(),
+            enable_launch_control_xl: true,
+            enable_launchpad_x: true,
+            piano_octave: 4,
+        }
+    }
+}
+
+impl Default for Audio {
+    fn default() -> Self {
+        Self {
+            backend: "jack".into(),
+            left: vec!["Scarlett 2i4 Analog Surround 4.0:playback_FL".into()],
+            right: vec!["Scarlett 2i4 Analog Surround 4.0:playback_FR".into()],
+            rate: 48_000,
+            period: 256,
+        }
+    }
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            dir: "state".into(),
+            interval: 60,
+            keep: 20,
+            restore: true,
+            format: "json".into(),
+        }
+    }
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self {
+            tempo: 120.0,
+            beats_per_bar: 4,
+            tap: String::new(),
+        }
+    }
+}
+
+impl Default for Record {
+    fn default() -> Self {
+        Self {
+            dir: "recordings".into(),
+            format: "i24".into(),
+            toggle: String::new(),
+        }
+    }
+}
+
+impl Default for Build {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            sources: "program".into(),
+            command: ["cargo", "build", "--release", "-p", "program"].map(String::from).to_vec(),
+        }
+    }
+}
+
+impl State {
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval)
+    }
+
+    pub fn encoding(&self) -> Result<Encoding> {
+        self.format.parse().map_err(anyhow::Error::msg)
+    }
+}
+
+impl Record {
+    pub fn format(&self) -> Result<wav::Format> {
+        self.format.parse()
+    }
+}
+
+impl Config {
+    pub const DEFAULT_PATH: &'static str = "synth.toml";
+
+    /// Loads the config at `path`, or `synth.toml` if it exists, or the defaults.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let path = match path {
+            Some(path) => path,
+            None if Path::new(Self::DEFAULT_PATH).exists() => Path::new(Self::DEFAULT_PATH),
+            None => return Ok(Self::default()),
+        };
+
+        let src = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read config {}", path.display()))?;
+        toml::from_str(&src)
+            .with_context(|| format!("failed to parse config {}", path.display()))
+    }
+
+    pub fn frame_interval(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.frame_rate.max(1) as f64)
+    }
+
+    pub fn debounce(&self) -> Duration {
+        Duration::from_millis(self.debounce)
+    }
+
+    pub fn crossfade(&self) -> f64 {
+        self.crossfade as f64 / 1000.0
+    }
+}
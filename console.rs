@@ -0,0 +1,19 @@
+use std::io::BufRead;
+use std::sync::mpsc::{self, Receiver};
+
+use crate::reactor::Waker;
+
+/// Reads commands typed into the terminal, one per line.
+pub fn spawn(waker: Waker) -> Receiver<String> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            if tx.send(line.trim().to_string()).is_err() {
+                break;
+            }
+            waker.wake();
+        }
+    });
+    rx
+}
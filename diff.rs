@@ -0,0 +1,77 @@
+use std::fmt;
+
+use serde_json::Value;
+
+/// What happened to the program state across a reload, found by comparing the
+/// state handed to the new library, after migrating it, with what it serializes back.
+#[derive(Default, Debug)]
+pub struct Report {
+    /// Fields the new program no longer has.
+    pub dropped: Vec<String>,
+    /// Fields the new program has that the old state didn't.
+    pub added: Vec<String>,
+    /// Fields that came back as the new program's default instead of the old value.
+    pub reset: Vec<String>,
+    /// Fields that came back as something else entirely.
+    pub changed: Vec<String>,
+}
+
+impl Report {
+    /// Compares serialized states. `default` is the new library's default state, if known.
+    /// Returns `None` if either side isn't JSON.
+    pub fn new(old: &[u8], new: &[u8], default: Option<&[u8]>) -> Option<Self> {
+        let mut old = serde_json::from_slice(old).ok()?;
+        let mut new = serde_json::from_slice(new).ok()?;
+        let mut default = default.and_then(|d| serde_json::from_slice(d).ok());
+        // the schema version isn't part of the state
+        for state in [Some(&mut old), Some(&mut new), default.as_mut()].into_iter().flatten() {
+            if let Value::Object(fields) = state {
+                fields.remove("version");
+            }
+        }
+
+        let mut report = Self::default();
+        report.walk(String::new(), &old, &new, default.as_ref());
+        Some(report)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dropped.is_empty() && self.added.is_empty() && self.reset.is_empty() && self.changed.is_empty()
+    }
+
+    fn walk(&mut self, path: String, old: &Value, new: &Value, default: Option<&Value>) {
+        let join = |key: &str| if path.is_empty() { key.to_string() } else { format!("{path}.{key}") };
+
+        match (old, new) {
+            (Value::Object(old), Value::Object(new)) => {
+                for (key, o) in old {
+                    match new.get(key) {
+                        Some(n) => self.walk(join(key), o, n, default.and_then(|d| d.get(key))),
+                        None => self.dropped.push(join(key)),
+                    }
+                }
+                for key in new.keys().filter(|k| !old.contains_key(*k)) {
+                    self.added.push(join(key));
+                }
+            },
+            (old, new) if old == new => {},
+            (_, new) if default == Some(new) => self.reset.push(path),
+            _ => self.changed.push(path),
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sections = [
+            ("dropped", &self.dropped),
+            ("added", &self.added),
+            ("reset to default", &self.reset),
+            ("changed", &self.changed),
+        ];
+        for (name, fields) in sections.into_iter().filter(|(_, f)| !f.is_empty()) {
+            writeln!(f, "  {name}: {}", fields.join(", "))?;
+        }
+        Ok(())
+    }
+}
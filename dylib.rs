@@ -0,0 +1,366 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::{Context, Result, anyhow};
+use libloading::Library;
+use libloading::os::unix::Symbol;
+use types::ffi::{Abi, Bytes, Devices, Encoding, Status, Transport};
+use types::launchpad_x as lpx;
+use types::launch_control_xl as lcx;
+
+/// An instance of the program inside a loaded library.
+///
+/// Every call into the library reports whether it panicked. After the first
+/// failure the instance is considered poisoned: it's never called again, the
+/// output is muted and the state can't be trusted until the next reload.
+/// Nothing is printed by the calls the audio thread makes; the failure is
+/// kept for `take_failure` instead.
+pub struct Program {
+    this: *mut (),
+    lib: Lib,
+    /// Position of the next frame to render.
+    pub transport: Transport,
+    devices: Devices,
+    failed: bool,
+    failure: Option<Failure>,
+}
+
+/// The call that poisoned a program.
+#[derive(Copy, Clone, Debug)]
+pub struct Failure {
+    call: &'static str,
+    status: Status,
+}
+
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "program: {} failed ({:?}), muting until the next reload", self.call, self.status)
+    }
+}
+
+unsafe impl Send for Program {}
+
+impl Program {
+    pub fn new(lib: Lib, rate: u32) -> Result<Self> {
+        let mut this = std::ptr::null_mut();
+        match (lib.default)(&mut this) {
+            Status::Ok => Ok(Self::init(this, lib, rate)),
+            status => Err(anyhow!("failed to create default state: {status:?}")),
+        }
+    }
+
+    /// Like `new`, but starting from a serialized state. Hands `lib` back on failure.
+    pub fn from_state(lib: Lib, state: &[u8], rate: u32) -> std::result::Result<Self, Box<(anyhow::Error, Lib)>> {
+        let mut this = std::ptr::null_mut();
+        let mut error = Bytes::empty();
+        match (lib.deserialize)(state.as_ptr(), state.len(), &mut this, &mut error) {
+            Status::Ok => Ok(Self::init(this, lib, rate)),
+            status => {
+                let message = String::from_utf8_lossy(error.as_slice()).into_owned();
+                if !error.ptr.is_null() {
+                    (lib.free_bytes)(error);
+                }
+                Err(Box::new((anyhow!("failed to deserialize state ({status:?}): {message}"), lib)))
+            },
+        }
+    }
+
+    fn init(this: *mut (), lib: Lib, rate: u32) -> Self {
+        let mut p = Self { this, lib, transport: Transport::new(rate), devices: Devices::default(), failed: false, failure: None };
+        p.notify_rate();
+        p
+    }
+
+    pub fn failed(&self) -> bool {
+        self.failed
+    }
+
+    /// The failure that poisoned the instance, if it hasn't been taken yet.
+    pub fn take_failure(&mut self) -> Option<Failure> {
+        self.failure.take()
+    }
+
+    fn check(&mut self, call: &'static str, status: Status) -> bool {
+        if status != Status::Ok && !self.failed {
+            self.failure = Some(Failure { call, status });
+            self.failed = true;
+        }
+        !self.failed
+    }
+
+    /// Serialized state, or `None` if the instance is poisoned or serialization failed.
+    /// Encoding a large state takes a while, so the audio thread uses `copy_state` instead.
+    pub fn serialize(&mut self, encoding: Encoding) -> Option<Vec<u8>> {
+        if self.failed {
+            return None;
+        }
+
+        let (status, state) = serialize(&self.lib.serialize, &self.lib.free_bytes, self.this, encoding);
+        // a serialization error doesn't poison the instance
+        if status == Status::Panic {
+            self.check("serialize", status);
+        }
+        state
+    }
+
+    /// A copy of the state to serialize elsewhere, or `None` if the instance is poisoned.
+    /// Only allocates, so it's fine to call between two blocks.
+    pub fn copy_state(&mut self) -> Option<StateCopy> {
+        if self.failed {
+            return None;
+        }
+
+        let mut this = std::ptr::null_mut();
+        let status = (self.lib.clone_state)(self.this, &mut this);
+        self.check("clone_state", status).then(|| StateCopy {
+            this,
+            serialize: self.lib.serialize.clone(),
+            free_bytes: self.lib.free_bytes.clone(),
+            drop: self.lib.drop.clone(),
+        })
+    }
+
+    /// Drops the instance and unloads the library.
+    pub fn unload(self) {
+        if !self.failed {
+            let _ = (self.lib.drop)(self.this);
+        }
+    }
+
+    pub fn rate(&self) -> u32 {
+        self.transport.rate
+    }
+
+    pub fn set_rate(&mut self, rate: u32) {
+        if rate != self.transport.rate {
+            self.transport.rate = rate;
+            self.notify_rate();
+        }
+    }
+
+    /// Tells the program about the current sample rate, e.g. after it was (re)loaded.
+    pub fn notify_rate(&mut self) {
+        if !self.failed {
+            let status = (self.lib.set_rate)(self.this, self.transport.rate as f64);
+            self.check("set_rate", status);
+        }
+    }
+
+    pub fn devices(&self) -> Devices {
+        self.devices
+    }
+
+    /// Tells the program which controllers are plugged in. LED frames for the
+    /// ones that aren't are skipped, but inputs still go through so replays and
+    /// scripts play without them.
+    pub fn set_devices(&mut self, devices: Devices) {
+        self.devices = devices;
+        if !self.failed {
+            let status = (self.lib.set_devices)(self.this, devices);
+            self.check("set_devices", status);
+        }
+    }
+
+    /// Renders one block, advancing the transport by its length.
+    pub fn process(&mut self, left: &mut [f32], right: &mut [f32]) {
+        let transport = self.transport;
+        self.transport = transport.offset(left.len() as u64);
+
+        if !self.failed {
+            let len = left.len().min(right.len());
+            let status = (self.lib.process)(self.this, &transport, left.as_mut_ptr(), right.as_mut_ptr(), len);
+            if self.check("process", status) {
+                return;
+            }
+        }
+
+        left.fill(0.0);
+        right.fill(0.0);
+    }
+
+    pub fn pad_in(&mut self, input: lpx::Input) {
+        if !self.failed {
+            let status = (self.lib.pad_in)(self.this, &self.transport, input);
+            self.check("pad_in", status);
+        }
+    }
+    pub fn pad_out(&mut self) -> Option<lpx::Output> {
+        let mut out = lpx::clear();
+        if !self.failed && self.devices.pad {
+            let status = (self.lib.pad_out)(self.this, &self.transport, &mut out);
+            return self.check("pad_out", status).then_some(out);
+        }
+        None
+    }
+
+    pub fn ctrl_in(&mut self, input: lcx::Input) {
+        if !self.failed {
+            let status = (self.lib.ctrl_in)(self.this, &self.transport, input);
+            self.check("ctrl_in", status);
+        }
+    }
+    pub fn ctrl_out(&mut self) -> Option<lcx::Output> {
+        let mut out = lcx::clear();
+        if !self.failed && self.devices.ctrl {
+            let status = (self.lib.ctrl_out)(self.this, &self.transport, &mut out);
+            return self.check("ctrl_out", status).then_some(out);
+        }
+        None
+    }
+}
+
+/// A copy of a program's state. It has to be serialized or dropped before the
+/// library it came from is unloaded.
+pub struct StateCopy {
+    this: *mut (),
+    serialize: Symbol<SerializeFn>,
+    free_bytes: Symbol<FreeBytesFn>,
+    drop: Symbol<DropFn>,
+}
+
+unsafe impl Send for StateCopy {}
+
+impl StateCopy {
+    pub fn serialize(self, encoding: Encoding) -> Option<Vec<u8>> {
+        serialize(&self.serialize, &self.free_bytes, self.this, encoding).1
+    }
+}
+
+impl Drop for StateCopy {
+    fn drop(&mut self) {
+        let _ = (self.drop)(self.this);
+    }
+}
+
+type SerializeFn = extern "C" fn(*mut (), Encoding, &mut Bytes) -> Status;
+type FreeBytesFn = extern "C" fn(Bytes);
+type DropFn = extern "C" fn(*mut ()) -> Status;
+
+/// Calls the library's `serialize`, copying the state out of its buffer.
+/// On an error the buffer holds the message instead, which is printed.
+fn serialize(serialize: &SerializeFn, free_bytes: &FreeBytesFn, this: *mut (), encoding: Encoding) -> (Status, Option<Vec<u8>>) {
+    let mut bytes = Bytes::empty();
+    let status = serialize(this, encoding, &mut bytes);
+    let out = bytes.as_slice().to_vec();
+    if !bytes.ptr.is_null() {
+        free_bytes(bytes);
+    }
+
+    match status {
+        Status::Ok => (status, Some(out)),
+        Status::Error => {
+            println!("failed to serialize state: {}", String::from_utf8_lossy(&out));
+            (status, None)
+        },
+        Status::Panic => (status, None),
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub struct Lib {
+    lib: Library,
+    process: Symbol<extern "C" fn(*mut (), &Transport, *mut f32, *mut f32, usize) -> Status>,
+    set_rate: Symbol<extern "C" fn(*mut (), f64) -> Status>,
+    set_devices: Symbol<extern "C" fn(*mut (), Devices) -> Status>,
+    pad_in: Symbol<extern "C" fn(*mut (), &Transport, lpx::Input) -> Status>,
+    pad_out: Symbol<extern "C" fn(*mut (), &Transport, &mut lpx::Output) -> Status>,
+    ctrl_in: Symbol<extern "C" fn(*mut (), &Transport, lcx::Input) -> Status>,
+    ctrl_out: Symbol<extern "C" fn(*mut (), &Transport, &mut lcx::Output) -> Status>,
+    default: Symbol<extern "C" fn(&mut *mut ()) -> Status>,
+    clone_state: Symbol<extern "C" fn(*mut (), &mut *mut ()) -> Status>,
+    serialize: Symbol<SerializeFn>,
+    deserialize: Symbol<extern "C" fn(*const u8, usize, &mut *mut (), &mut Bytes) -> Status>,
+    migrate_state: Symbol<extern "C" fn(*const u8, usize, &mut Bytes) -> Status>,
+    free_bytes: Symbol<FreeBytesFn>,
+    drop: Symbol<DropFn>,
+}
+
+impl Lib {
+    /// Serialized default state, without keeping an instance around.
+    pub fn default_state(&self, encoding: Encoding) -> Option<Vec<u8>> {
+        let mut this = std::ptr::null_mut();
+        if (self.default)(&mut this) != Status::Ok {
+            return None;
+        }
+
+        let (_, state) = serialize(&self.serialize, &self.free_bytes, this, encoding);
+        let _ = (self.drop)(this);
+        state
+    }
+
+    /// `state` in either encoding, migrated to this library's version and
+    /// re-encoded as JSON without the version tag.
+    pub fn migrate_state(&self, state: &[u8]) -> Result<Vec<u8>> {
+        let mut out = Bytes::empty();
+        let status = (self.migrate_state)(state.as_ptr(), state.len(), &mut out);
+        let bytes = out.as_slice().to_vec();
+        if !out.ptr.is_null() {
+            (self.free_bytes)(out);
+        }
+
+        match status {
+            Status::Ok => Ok(bytes),
+            status => Err(anyhow!("failed to migrate state ({status:?}): {}", String::from_utf8_lossy(&bytes))),
+        }
+    }
+
+    /// Loads a private copy of the library at `path`.
+    ///
+    /// dlopen hands back the existing mapping for a path that's already loaded,
+    /// and the file may be overwritten while mapped, so each reload gets its own copy.
+    pub fn load_copy(path: impl AsRef<Path>) -> Result<Self> {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+        let n = COUNT.fetch_add(1, Ordering::Relaxed);
+        let copy = std::env::temp_dir().join(format!("synth-{}-{n}.so", std::process::id()));
+        std::fs::copy(path.as_ref(), &copy)
+            .with_context(|| format!("failed to copy {} to {}", path.as_ref().display(), copy.display()))?;
+
+        // the mapping stays valid once the file is gone
+        let lib = Self::load(&copy);
+        let _ = std::fs::remove_file(&copy);
+        lib
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        unsafe {
+            let lib = Library::new(path)?;
+
+            // nothing else can be trusted until we know both sides agree on the types
+            let abi = required::<extern "C" fn() -> Abi>(&lib, b"abi\0")
+                .map_err(|_| anyhow!("refusing to load {}: no `abi` export, it predates the C ABI; rebuild it", path.display()))?;
+            let (theirs, ours) = (abi(), Abi::current());
+            if theirs != ours {
+                anyhow::bail!(
+                    "refusing to load {}: it was built against ABI v{} (layout {:016x}) but the host expects v{} (layout {:016x}); \
+                     rebuild the host and the library from the same `types` crate",
+                    path.display(), theirs.version, theirs.layout, ours.version, ours.layout,
+                );
+            }
+
+            Ok(Self {
+                process: required(&lib, b"process\0")?,
+                set_rate: required(&lib, b"set_rate\0")?,
+                set_devices: required(&lib, b"set_devices\0")?,
+                pad_in: required(&lib, b"pad_in\0")?,
+                pad_out: required(&lib, b"pad_out\0")?,
+                ctrl_in: required(&lib, b"ctrl_in\0")?,
+                ctrl_out: required(&lib, b"ctrl_out\0")?,
+                default: required(&lib, b"default\0")?,
+                clone_state: required(&lib, b"clone_state\0")?,
+                serialize: required(&lib, b"serialize\0")?,
+                deserialize: required(&lib, b"deserialize\0")?,
+                migrate_state: required(&lib, b"migrate_state\0")?,
+                free_bytes: required(&lib, b"free_bytes\0")?,
+                drop: required(&lib, b"drop\0")?,
+
+                lib
+            })
+        }
+    }
+}
+
+unsafe fn required<T>(lib: &Library, name: &[u8]) -> Result<Symbol<T>> {
+    Ok(lib.get::<T>(name)?.into_raw())
+}
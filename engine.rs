@@ -0,0 +1,393 @@
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use rtrb::{Consumer, Producer, PushError, RingBuffer};
+use types::ffi::{Devices, Encoding};
+use types::launchpad_x as lpx;
+use types::launch_control_xl as lcx;
+
+use crate::diff::Report;
+use crate::dylib::{Failure, Lib, Program, StateCopy};
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Input {
+    Pad(lpx::Input),
+    Ctrl(lcx::Input),
+    /// Sets the transport's tempo in BPM.
+    Tempo(f64),
+    /// Sets the transport's beats per bar.
+    Meter(u32),
+    /// A controller was plugged in or unplugged.
+    Devices(Devices),
+}
+
+impl Input {
+    pub fn apply(self, p: &mut Program) {
+        match self {
+            Input::Pad(input) => p.pad_in(input),
+            Input::Ctrl(input) => p.ctrl_in(input),
+            Input::Tempo(bpm) => p.transport.tempo = bpm,
+            Input::Meter(beats) => p.transport.set_meter(beats),
+            Input::Devices(devices) => p.set_devices(devices),
+        }
+    }
+}
+
+/// Splits a program into its audio thread and control thread halves.
+///
+/// The audio thread owns the program and never waits on the control thread:
+/// inputs arrive through a wait-free queue, and LED frames and copies of the
+/// state are published back the same way. Copies are only taken on request,
+/// between two blocks, and encoded on the control thread, so the program keeps
+/// playing while they're saved.
+///
+/// Inputs are timestamped on arrival and played back one block late at the same
+/// offset they arrived at into the previous block, trading a fixed block of
+/// latency for timing that doesn't jitter with the block size.
+///
+/// Reloaded programs are queued to the audio thread, which crossfades from the
+/// old one over `crossfade` seconds before handing it back to be unloaded.
+/// State is carried over and saved in `encoding`.
+pub fn new(mut program: Program, crossfade: f64, encoding: Encoding) -> (Engine, Handle) {
+    let rate = program.rate();
+    let last_good = program.serialize(encoding).map(|state| (state, 0));
+    let (inputs_tx, inputs_rx) = RingBuffer::new(256);
+    let (pad_tx, pad_rx) = RingBuffer::new(4);
+    let (ctrl_tx, ctrl_rx) = RingBuffer::new(4);
+    let (requests_tx, requests_rx) = RingBuffer::new(1);
+    let (states_tx, states_rx) = RingBuffer::new(1);
+    let (swaps_tx, swaps_rx) = RingBuffer::new(1);
+    let (retired_tx, retired_rx) = RingBuffer::new(8);
+    let (failures_tx, failures_rx) = RingBuffer::new(4);
+
+    let engine = Engine {
+        program: Box::new(program),
+        inputs: inputs_rx,
+        pad_out: pad_tx,
+        ctrl_out: ctrl_tx,
+        requests: requests_rx,
+        states: states_tx,
+        swaps: swaps_rx,
+        retired: retired_tx,
+        failures: failures_tx,
+        crossfade,
+        fading: None,
+        scratch: (vec![0.0; 4096], vec![0.0; 4096]),
+        last_block: Instant::now(),
+        applied: 0,
+        history: vec![None; Engine::HISTORY],
+    };
+    let handle = Handle {
+        inputs: inputs_tx,
+        pad_out: pad_rx,
+        ctrl_out: ctrl_rx,
+        requests: requests_tx,
+        states: states_rx,
+        next_request: 0,
+        swaps: swaps_tx,
+        retired: retired_rx,
+        failures: failures_rx,
+        last_good,
+        rate,
+        encoding,
+    };
+    (engine, handle)
+}
+
+/// A copy of the state, answering the request with the same id.
+struct Snapshot {
+    id: u64,
+    state: Option<StateCopy>,
+    /// Inputs the state includes.
+    applied: u64,
+    rate: u32,
+}
+
+/// Audio thread half.
+pub struct Engine {
+    program: Box<Program>,
+    inputs: Consumer<(Instant, Input)>,
+    pad_out: Producer<lpx::Output>,
+    ctrl_out: Producer<lcx::Output>,
+
+    requests: Consumer<u64>,
+    states: Producer<Snapshot>,
+
+    /// Reloaded programs, with how many inputs the state they were created from includes.
+    swaps: Consumer<(Box<Program>, Option<u64>)>,
+    retired: Producer<Box<Program>>,
+    /// Reported by the control thread, since printing could block.
+    failures: Producer<Failure>,
+    crossfade: f64,
+    fading: Option<Fade>,
+    scratch: (Vec<f32>, Vec<f32>),
+    /// When the previous block was requested.
+    last_block: Instant,
+
+    /// Inputs applied so far, and the latest of them, indexed by count.
+    applied: u64,
+    history: Vec<Option<Input>>,
+}
+
+/// A replaced program that's still playing while it fades out.
+struct Fade {
+    program: Box<Program>,
+    pos: usize,
+    len: usize,
+}
+
+impl Engine {
+    const HISTORY: usize = 256;
+
+    pub fn process(&mut self, rate: u32, left: &mut [f32], right: &mut [f32]) {
+        self.program.set_rate(rate);
+
+        if let Ok((mut new, applied)) = self.swaps.pop() {
+            let p = &self.program;
+            new.transport = p.transport;
+            new.set_rate(rate);
+            new.set_devices(p.devices());
+
+            // catch up on what was played while the reload was in progress
+            if let Some(applied) = applied {
+                let from = applied.max(self.applied.saturating_sub(Self::HISTORY as u64));
+                for i in from..self.applied {
+                    if let Some(input) = self.history[i as usize % Self::HISTORY] {
+                        input.apply(&mut new);
+                    }
+                }
+            }
+
+            self.retire();
+            let len = (self.crossfade * rate as f64) as usize;
+            self.fading = Some(Fade { program: std::mem::replace(&mut self.program, new), pos: 0, len });
+        }
+
+        let now = Instant::now();
+        let len = left.len();
+        let mut pos = 0;
+        loop {
+            // where in this block the next input lands, relative to when it arrived in the last one
+            let next = self.inputs.peek().ok().map(|(at, _)| {
+                let offset = (at.saturating_duration_since(self.last_block).as_secs_f64() * rate as f64) as usize;
+                offset.clamp(pos, len)
+            });
+
+            let end = next.unwrap_or(len);
+            if end > pos {
+                self.program.process(&mut left[pos..end], &mut right[pos..end]);
+                pos = end;
+            }
+
+            let Some(_) = next else { break };
+            let Ok((_, input)) = self.inputs.pop() else { break };
+            input.apply(&mut self.program);
+            self.history[self.applied as usize % Self::HISTORY] = Some(input);
+            self.applied += 1;
+            // inputs go to both, so e.g. notes released mid-fade don't keep ringing.
+            // the fading program only hears them at the start of its next block.
+            if let Some(fade) = &mut self.fading {
+                input.apply(&mut fade.program);
+            }
+        }
+        self.last_block = now;
+
+        self.fade(left, right);
+
+        // if the control thread hasn't caught up, it'll get the next one
+        if let Some(output) = self.program.pad_out() {
+            let _ = self.pad_out.push(output);
+        }
+        if let Some(output) = self.program.ctrl_out() {
+            let _ = self.ctrl_out.push(output);
+        }
+
+        let fading = self.fading.as_mut().map(|f| &mut f.program);
+        for p in [Some(&mut self.program), fading].into_iter().flatten() {
+            if let Some(failure) = p.take_failure() {
+                let _ = self.failures.push(failure);
+            }
+        }
+
+        // the only place anything but audio and inputs reaches the program,
+        // so the state is consistent with everything played up to here
+        if let Ok(id) = self.requests.pop() {
+            let state = self.program.copy_state();
+            let _ = self.states.push(Snapshot { id, state, applied: self.applied, rate: self.program.rate() });
+        }
+    }
+
+    /// Mixes the fading program under the block already rendered by the current one.
+    fn fade(&mut self, left: &mut [f32], right: &mut [f32]) {
+        let Some(fade) = &mut self.fading else { return };
+
+        let (old_left, old_right) = &mut self.scratch;
+        let mut i = 0;
+        while i < left.len() && fade.pos < fade.len {
+            let n = (left.len() - i).min(old_left.len());
+            fade.program.process(&mut old_left[..n], &mut old_right[..n]);
+
+            for j in 0..n {
+                let g = (fade.pos as f32 / fade.len as f32).min(1.0);
+                left[i + j] = left[i + j] * g + old_left[j] * (1.0 - g);
+                right[i + j] = right[i + j] * g + old_right[j] * (1.0 - g);
+                fade.pos += 1;
+            }
+            i += n;
+        }
+
+        if fade.pos >= fade.len {
+            self.retire();
+        }
+    }
+
+    /// Hands the fading program back to the control thread, since unloading it
+    /// here would block. If the queue is full it stays (silent) until the next block.
+    fn retire(&mut self) {
+        if let Some(fade) = self.fading.take() {
+            if let Err(PushError::Full(program)) = self.retired.push(fade.program) {
+                self.fading = Some(Fade { program, pos: fade.len, len: fade.len });
+            }
+        }
+    }
+}
+
+/// Control thread half.
+pub struct Handle {
+    inputs: Producer<(Instant, Input)>,
+    pad_out: Consumer<lpx::Output>,
+    ctrl_out: Consumer<lcx::Output>,
+
+    requests: Producer<u64>,
+    states: Consumer<Snapshot>,
+    next_request: u64,
+
+    swaps: Producer<(Box<Program>, Option<u64>)>,
+    retired: Consumer<Box<Program>>,
+    failures: Consumer<Failure>,
+
+    /// Last state successfully serialized and the inputs it includes, used to
+    /// restore a poisoned program on reload.
+    last_good: Option<(Vec<u8>, u64)>,
+    rate: u32,
+    encoding: Encoding,
+}
+
+impl Handle {
+    /// How long to wait for the audio thread to answer a state request, e.g. if it stalled.
+    const SNAPSHOT_TIMEOUT: Duration = Duration::from_secs(1);
+
+    /// Queues an input that arrived `at`, to be played at the matching offset into the next block.
+    pub fn send(&mut self, at: Instant, input: Input) {
+        if self.inputs.push((at, input)).is_err() {
+            println!("input queue full, dropping {input:?}");
+        }
+    }
+
+    /// Latest LED frame published by the audio thread, if there is a new one.
+    pub fn pad_out(&mut self) -> Option<lpx::Output> {
+        std::iter::from_fn(|| self.pad_out.pop().ok()).last()
+    }
+    pub fn ctrl_out(&mut self) -> Option<lcx::Output> {
+        std::iter::from_fn(|| self.ctrl_out.pop().ok()).last()
+    }
+
+    /// Asks the audio thread to copy the state after its next block and waits for it.
+    fn snapshot(&mut self) -> Option<Snapshot> {
+        let id = self.next_request;
+        // an earlier request that timed out is still waiting for the audio thread
+        if self.requests.push(id).is_err() {
+            return None;
+        }
+        self.next_request += 1;
+
+        let deadline = Instant::now() + Self::SNAPSHOT_TIMEOUT;
+        while Instant::now() < deadline {
+            match self.states.pop() {
+                Ok(snapshot) if snapshot.id == id => return Some(snapshot),
+                // the late answer to one that timed out
+                Ok(_) => {},
+                Err(_) => std::thread::sleep(Duration::from_micros(100)),
+            }
+        }
+        None
+    }
+
+    /// Current state for a snapshot, or the last good one if the program has failed since.
+    pub fn serialize(&mut self) -> Option<Vec<u8>> {
+        self.state().map(|(state, _)| state)
+    }
+
+    /// Like `serialize`, with the number of inputs the state includes.
+    fn state(&mut self) -> Option<(Vec<u8>, u64)> {
+        match self.snapshot() {
+            Some(snapshot) => {
+                self.rate = snapshot.rate;
+                // the copy came from the current program, so its library is still loaded
+                if let Some(state) = snapshot.state.and_then(|copy| copy.serialize(self.encoding)) {
+                    self.last_good = Some((state, snapshot.applied));
+                }
+            },
+            None => println!("the audio thread didn't answer, using the last good state"),
+        }
+        self.last_good.clone()
+    }
+
+    /// Swaps in a copy of the library at `path`, carrying state over through `serialize`/`deserialize`.
+    /// The current program keeps running until the new one has been created successfully, then
+    /// the audio thread replays the inputs it got in the meantime and crossfades between the two.
+    pub fn reload(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        self.collect();
+
+        let lib = Lib::load_copy(path)?;
+        let (state, mut applied) = self.state().unzip();
+        let default = lib.default_state(Encoding::Json);
+        // what the new library should make of the old state, so renames and other migrations aren't reported
+        let old = state.as_ref().and_then(|state| lib.migrate_state(state).ok());
+
+        let mut p = match &state {
+            Some(state) => match Program::from_state(lib, state, self.rate) {
+                Ok(p) => p,
+                Err(e) => {
+                    let (e, lib) = *e;
+                    println!("{e}, starting from the default state");
+                    applied = None;
+                    Program::new(lib, self.rate)?
+                },
+            },
+            None => Program::new(lib, self.rate)?,
+        };
+
+        if let Some(old) = &old {
+            let report = p.serialize(Encoding::Json).and_then(|new| Report::new(old, &new, default.as_deref()));
+            if let Some(report) = report.filter(|r| !r.is_empty()) {
+                print!("state changed on reload:\n{report}");
+            }
+        }
+        if let Some(failure) = p.take_failure() {
+            println!("{failure}");
+        }
+
+        if let Err(PushError::Full((p, _))) = self.swaps.push((Box::new(p), applied)) {
+            p.unload();
+            anyhow::bail!("previous reload hasn't been picked up by the audio thread yet");
+        }
+        Ok(())
+    }
+
+    /// Unloads programs the audio thread has finished fading out, and reports any that failed.
+    pub fn collect(&mut self) {
+        while let Ok(failure) = self.failures.pop() {
+            println!("{failure}");
+        }
+        // late answers to requests that timed out, which have to go before their library does
+        while self.states.pop().is_ok() {}
+        while let Ok(mut p) = self.retired.pop() {
+            if let Some(failure) = p.take_failure() {
+                println!("{failure}");
+            }
+            p.unload();
+        }
+    }
+}
@@ -3,178 +3,480 @@
 use std::error::Error;
 use std::f32::consts::PI;
 use std::mem;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::Instant;
-
-use anyhow::Result;
-use inotify::{Inotify, WatchMask};
-use libloading::Library;
-use libloading::os::unix::Symbol;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use builder::Builder;
+use cli::{Cli, Command};
+use clock::TapTempo;
+use config::Config;
+use dylib::{Lib, Program};
+use engine::Input;
 use midi::{Midi, LaunchpadX, LaunchControlXL};
-use spin::Mutex;
-
+use reactor::Reactor;
+use session::{Log, Replay};
+use snapshot::Snapshots;
+use types::ffi::{Devices, Encoding};
+use watch::Watcher;
+
+mod audio;
+mod builder;
+mod cli;
+mod clock;
+mod config;
+mod console;
+mod diff;
+mod dylib;
+mod engine;
 mod midi;
+mod reactor;
+mod recorder;
+mod render;
+mod session;
+mod smf;
+mod snapshot;
+mod watch;
+mod wav;
 
 fn main() -> Result<()> {
-    let mut ctrl: Midi<LaunchControlXL> = Midi::open("Launch Control XL:Launch Control XL")?;
-    let mut pad: Midi<LaunchpadX> = Midi::open("Launchpad X:Launchpad X LPX MIDI")?;
-
-    let mut inotify = Inotify::init()?;
-    inotify.add_watch(std::env::current_dir()?.join("target/release"), WatchMask::CREATE)?;
-
-    let lib = Lib::load()?;
-    let this = (lib.default)();
-    let program = Arc::new(Mutex::new(Program {
-        this,
-        lib,
-        t: 0.0,
-    }));
-    let _program = Arc::clone(&program);
-
-
-    let (jack, _) = jack::Client::new("synth", jack::ClientOptions::NO_START_SERVER)?;
-    let mut out_left = jack.register_port("out_left", jack::AudioOut)?;
-    let mut out_right = jack.register_port("out_right", jack::AudioOut)?;
-
-    let process = jack::ClosureProcessHandler::new(move |client, ps| -> jack::Control {
-        const RATE: f64 = 48_000.0; // TODO: make dynamic
-
-        let mut p = _program.lock();
-
-        let mut out_left = out_left.as_mut_slice(ps);
-        let mut out_right = out_right.as_mut_slice(ps);
-
-        for frame in out_left.iter_mut().zip(out_right.iter_mut()) {
-            p.t += 1.0 / RATE;
-            let (l, r) = (p.lib.sample)(p.this, p.t);
-            *frame.0 = l as f32;
-            *frame.1 = r as f32;
-        }
+    let cli = Cli::parse();
+    let mut config = Config::load(cli.config.as_deref())?;
+
+    match cli.command.unwrap_or(Command::Run(Default::default())) {
+        Command::Run(mut args) => {
+            args.apply(&mut config);
+            run(config, args)
+        },
+        Command::Ports => ports(),
+        Command::Diff { a, b } => diff_wav(&a, &b),
+        Command::Render(args) => render(config, args),
+        Command::State(args) => state(config, args),
+    }
+}
 
-        jack::Control::Continue
-    });
-    let _process = jack.activate_async(Notifications, process);
-
-    let (jack, _) = jack::Client::new("synth", jack::ClientOptions::NO_START_SERVER)?;
-    jack.connect_ports_by_name("synth:out_left", "Scarlett 2i4 Analog Surround 4.0:playback_FL")?;
-    jack.connect_ports_by_name("synth:out_right", "Scarlett 2i4 Analog Surround 4.0:playback_FR")?;
-
-    loop {
-        let mut buf = [0; 256];
-        match inotify.read_events(&mut buf) {
-            Ok(events) => for event in events {
-                if event.name.unwrap().to_str().unwrap() == "libprogram.so" {
-                    let mut p = program.lock();
-
-                    // serialize and drop `this`
-                    let serialized = (p.lib.serialize)(p.this);
-                    (p.lib.drop)(p.this);
-
-                    // reload .so, carefully...
-                    let lib = mem::replace(&mut p.lib, unsafe { mem::zeroed() });
-                    drop(lib);
-                    let lib = Lib::load()?;
-                    let zeroed = mem::replace(&mut p.lib, lib);
-                    mem::forget(zeroed);
-
-                    // deserialize `this`
-                    p.this = match (p.lib.deserialize)(&serialized) {
-                        Ok(this) => this,
-                        Err(e) => {
-                            println!("failed to deserialize state: {e}");
-                            (p.lib.default)()
-                        },
-                    };
-
-                    println!("reloaded libprogram.so");
+fn run(config: Config, args: cli::Run) -> Result<()> {
+    let reactor = Reactor::new();
+    let mut ctrl = config.midi.enable_launch_control_xl
+        .then(|| Midi::<LaunchControlXL>::open(&config.midi.launch_control_xl, reactor.waker()));
+    let mut pad = config.midi.enable_launchpad_x
+        .then(|| Midi::<LaunchpadX>::open(&config.midi.launchpad_x, reactor.waker()));
+    let devices = |pad: &Option<Midi<_>>, ctrl: &Option<Midi<_>>| Devices {
+        pad: pad.as_ref().is_some_and(Midi::connected),
+        ctrl: ctrl.as_ref().is_some_and(Midi::connected),
+    };
+
+    // with host builds enabled, reloads follow successful builds instead of the library changing
+    let mut builder = match config.build.enabled {
+        true => Some(Builder::new(&config.build, config.debounce(), reactor.waker())?),
+        false => None,
+    };
+    let mut watcher = match builder {
+        Some(_) => None,
+        None => Some(Watcher::new(&config.watch, &config.library, config.debounce(), reactor.waker())?),
+    };
+
+    let encoding = config.state.encoding()?;
+    let mut snapshots = Snapshots::new(&config.state.dir, config.state.keep);
+
+    let tap = control_input(&config.clock.tap)?;
+    let mut tap_tempo = TapTempo::default();
+
+    let toggle_record = control_input(&config.record.toggle)?;
+    let (mut recorder, mut record_tap) = recorder::new(&config.record.dir, config.record.format()?);
+    let console = console::spawn(reactor.waker());
+
+    let lib = Lib::load_copy(&config.library)?;
+    let mut program = match &args.state {
+        Some(path) => Program::from_state(lib, &std::fs::read(path)?, 48_000).map_err(|e| e.0)?,
+        None => match snapshots.latest()?.filter(|_| config.state.restore) {
+            Some(path) => {
+                println!("restoring {}", path.display());
+                let state = std::fs::read(&path)?;
+                match Program::from_state(lib, &state, 48_000) {
+                    Ok(p) => p,
+                    Err(e) => {
+                        let (e, lib) = *e;
+                        println!("{e}");
+                        Program::new(lib, 48_000)?
+                    },
                 }
             },
-            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {},
-            Err(e) => anyhow::bail!(e),
+            None => Program::new(lib, 48_000)?,
+        },
+    };
+    Input::Tempo(config.clock.tempo).apply(&mut program);
+    Input::Meter(config.clock.beats_per_bar).apply(&mut program);
+    Input::Devices(devices(&pad, &ctrl)).apply(&mut program);
+    let (mut engine, mut handle) = engine::new(program, config.crossfade(), encoding);
+
+    let quit = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(signal_hook::consts::SIGINT, Arc::clone(&quit))?;
+    signal_hook::flag::register(signal_hook::consts::SIGTERM, Arc::clone(&quit))?;
+    let mut next_save = Instant::now() + config.state.interval();
+    let mut next_frame = Instant::now();
+
+    // session time starts with the audio, so logs line up with offline renders
+    let start = Instant::now();
+    let mut log = match &args.log {
+        Some(path) => Some(Log::create(path, start, config.midi.piano_octave)?),
+        None => None,
+    };
+    if let Some(log) = &mut log {
+        log.write(start, Input::Tempo(config.clock.tempo));
+        log.write(start, Input::Meter(config.clock.beats_per_bar));
+        log.write(start, Input::Devices(devices(&pad, &ctrl)));
+    }
+    let mut replay = match &args.replay {
+        Some(path) => Some(Replay::load(path, start, config.midi.piano_octave)?),
+        None => None,
+    };
+
+    let mut audio = audio::open(&config.audio)?;
+    audio.start(Box::new(move |rate, out_left, out_right| {
+        engine.process(rate, out_left, out_right);
+        record_tap.write(rate, out_left, out_right);
+    }))?;
+
+    while !quit.load(Ordering::Relaxed) {
+        handle.collect();
+        let changed = match (&mut builder, &mut watcher) {
+            (Some(builder), _) => builder.poll(),
+            (_, Some(w)) => match w.poll() {
+                Ok(changed) => changed,
+                Err(e) => {
+                    println!("{e}, {} is no longer reloaded", config.library.display());
+                    watcher = None;
+                    false
+                },
+            },
+            _ => false,
         };
+        if changed {
+            match handle.reload(&config.library) {
+                Ok(()) => {
+                    println!("reloaded {}", config.library.display());
+                    recorder.mark("reload");
+                },
+                Err(e) => {
+                    println!("failed to reload {}: {e}", config.library.display());
+                    recorder.mark("reload failed");
+                },
+            }
+        }
 
-        {
-            for input in pad.recv() {
-                let p = program.lock();
-                (p.lib.pad_in)(p.this, p.t, input);
+        let mut toggle = false;
+        for command in console.try_iter() {
+            match command.as_str() {
+                "r" | "record" => toggle = !toggle,
+                "q" | "quit" => quit.store(true, Ordering::Relaxed),
+                "" => {},
+                other => println!("unknown command: {other} (expected record or quit)"),
             }
+        }
 
-            let p = program.lock();
-            let output = (p.lib.pad_out)(p.this, p.t);
-            pad.send(output);
+        let mut inputs = Vec::new();
+        if let Some(pad) = &mut pad {
+            inputs.extend(pad.recv().map(|(at, i)| (at, Input::Pad(i))));
+        }
+        if let Some(ctrl) = &mut ctrl {
+            inputs.extend(ctrl.recv().map(|(at, i)| (at, Input::Ctrl(i))));
+        }
+        if let Some(replay) = &mut replay {
+            inputs.extend(replay.due(Instant::now()));
+        }
+        let pad_changed = pad.as_mut().is_some_and(Midi::poll);
+        let ctrl_changed = ctrl.as_mut().is_some_and(Midi::poll);
+        if pad_changed || ctrl_changed {
+            inputs.push((Instant::now(), Input::Devices(devices(&pad, &ctrl))));
+        }
+        inputs.sort_by_key(|(at, _)| *at);
+        for (at, mut input) in inputs {
+            if Some(input) == toggle_record {
+                toggle = !toggle;
+                continue;
+            }
+            if Some(input) == tap {
+                let Some(bpm) = tap_tempo.tap(at) else { continue };
+                println!("tempo {bpm:.1}");
+                input = Input::Tempo(bpm);
+            }
+            if let Some(log) = &mut log {
+                log.write(at, input);
+            }
+            handle.send(at, input);
+        }
+        if toggle {
+            if let Err(e) = recorder.toggle() {
+                println!("failed to toggle recording: {e}");
+            }
         }
 
-        {
-            use types::launch_control_xl::*;
+        let now = Instant::now();
+        if now >= next_frame {
+            next_frame += config.frame_interval();
+            // skip frames rather than bursting to catch up after a stall
+            if next_frame <= now {
+                next_frame = now + config.frame_interval();
+            }
 
-            for input in ctrl.recv() {
-                let p = program.lock();
-                (p.lib.ctrl_in)(p.this, p.t, input);
+            if let Some(mut output) = handle.pad_out() {
+                if let Some(builder) = &builder {
+                    // top right logo LED
+                    output[80] = builder.status().color();
+                }
+                if let Some(pad) = &mut pad {
+                    pad.send(output);
+                }
             }
+            if let (Some(output), Some(ctrl)) = (handle.ctrl_out(), &mut ctrl) {
+                ctrl.send(output);
+            }
+        }
 
-            let p = program.lock();
-            let output = (p.lib.ctrl_out)(p.this, p.t);
-            ctrl.send(output);
+        if now >= next_save {
+            autosave(&mut snapshots, &mut handle);
+            next_save = now + config.state.interval();
         }
 
-        std::thread::sleep(std::time::Duration::from_millis(5));
+        let settle = builder.as_ref().and_then(Builder::deadline)
+            .or(watcher.as_ref().and_then(Watcher::deadline));
+        let replay_next = replay.as_mut().and_then(Replay::deadline);
+        let deadline = [Some(next_frame), Some(next_save), settle, replay_next].into_iter().flatten().min().unwrap();
+        reactor.wait(deadline);
+    }
+
+    autosave(&mut snapshots, &mut handle);
+    recorder.stop()?;
+    if let Some(log) = log {
+        log.finish()?;
     }
+    Ok(())
 }
 
-struct Program {
-    this: *mut (),
-    lib: Lib,
-    t: f64,
+/// Parses an input from the config that the host handles itself, empty for none.
+fn control_input(spec: &str) -> Result<Option<Input>> {
+    match spec {
+        "" => Ok(None),
+        spec => render::parse_input(spec).map(Some).with_context(|| format!("invalid control input '{spec}'")),
+    }
 }
 
-unsafe impl Send for Program {}
-
-#[allow(clippy::type_complexity)]
-struct Lib {
-    lib: Library,
-    sample: Symbol<fn(*mut (), f64) -> (f64, f64)>,
-    pad_in: Symbol<fn(*mut (), f64, types::launchpad_x::Input)>,
-    pad_out: Symbol<fn(*mut (), f64) -> types::launchpad_x::Output>,
-    ctrl_in: Symbol<fn(*mut (), f64, types::launch_control_xl::Input)>,
-    ctrl_out: Symbol<fn(*mut (), f64) -> types::launch_control_xl::Output>,
-    default: Symbol<fn() -> *mut ()>,
-    serialize: Symbol<fn(*mut ()) -> Vec<u8>>,
-    deserialize: Symbol<fn(&[u8]) -> std::result::Result<*mut (), Box<dyn Error>>>,
-    drop: Symbol<fn(*mut ())>,
+fn autosave(snapshots: &mut Snapshots, handle: &mut engine::Handle) {
+    let Some(state) = handle.serialize() else { return };
+    match snapshots.save(&state) {
+        Ok(Some(path)) => println!("saved {}", path.display()),
+        Ok(None) => {},
+        Err(e) => println!("failed to save state: {e}"),
+    }
 }
 
-impl Lib {
-    pub fn load() -> Result<Self> {
-        unsafe {
-            let lib = Library::new("target/release/libprogram.so")?;
-            Ok(Self {
-                sample: lib.get::<fn(*mut (), f64) -> (f64, f64)>(b"sample\0")?.into_raw(),
-                pad_in: lib.get::<fn(*mut (), f64, types::launchpad_x::Input)>(b"pad_in\0")?.into_raw(),
-                pad_out: lib.get::<fn(*mut (), f64) -> types::launchpad_x::Output>(b"pad_out\0")?.into_raw(),
-                ctrl_in: lib.get::<fn(*mut (), f64, types::launch_control_xl::Input)>(b"ctrl_in\0")?.into_raw(),
-                ctrl_out: lib.get::<fn(*mut (), f64) -> types::launch_control_xl::Output>(b"ctrl_out\0")?.into_raw(),
-                default: lib.get::<fn() -> *mut ()>(b"default\0")?.into_raw(),
-                serialize: lib.get::<fn(*mut ()) -> Vec<u8>>(b"serialize\0")?.into_raw(),
-                deserialize: lib.get::<fn(&[u8]) -> std::result::Result<*mut (), Box<dyn Error>>>(b"deserialize\0")?.into_raw(),
-                drop: lib.get::<fn(*mut ())>(b"drop\0")?.into_raw(),
-
-                lib
-            })
-        }
+fn ports() -> Result<()> {
+    let (inputs, outputs) = Midi::<LaunchpadX>::list()?;
+    for port in inputs {
+        println!("MIDI in:   '{port}'");
+    }
+    for port in outputs {
+        println!("MIDI out:  '{port}'");
     }
+
+    match audio::Jack::list() {
+        Ok((outputs, inputs)) => {
+            for port in outputs {
+                println!("JACK out:  '{port}'");
+            }
+            for port in inputs {
+                println!("JACK in:   '{port}'");
+            }
+        },
+        Err(e) => println!("JACK: {e}"),
+    }
+
+    Ok(())
+}
+
+fn render(config: Config, args: cli::Render) -> Result<()> {
+    let lib = Lib::load(args.lib.unwrap_or(config.library))?;
+    let mut program = match args.state {
+        Some(path) => Program::from_state(lib, &std::fs::read(path)?, args.rate).map_err(|e| e.0)?,
+        None => Program::new(lib, args.rate)?,
+    };
+    Input::Tempo(config.clock.tempo).apply(&mut program);
+    Input::Meter(config.clock.beats_per_bar).apply(&mut program);
+
+    let events = match args.script {
+        Some(path) => session::load_events(path, config.midi.piano_octave)?,
+        None => vec![],
+    };
+
+    let opts = render::Options {
+        rate: args.rate,
+        duration: args.duration,
+        format: args.format,
+    };
+    render::render(program, &args.out, &events, opts)?;
+    println!("rendered {}", args.out.display());
+    Ok(())
 }
 
-struct Notifications;
-impl jack::NotificationHandler for Notifications {
-    fn shutdown(&mut self, status: jack::ClientStatus, reason: &str) {}
+/// Compares two WAV files sample by sample.
+fn diff_wav(a: &Path, b: &Path) -> Result<()> {
+    let (rate_a, left_a, right_a) = wav::read(a).with_context(|| format!("failed to read {}", a.display()))?;
+    let (rate_b, left_b, right_b) = wav::read(b).with_context(|| format!("failed to read {}", b.display()))?;
+    if rate_a != rate_b {
+        anyhow::bail!("sample rates differ: {rate_a} vs {rate_b}");
+    }
+    if left_a.len() != left_b.len() {
+        println!("lengths differ: {} vs {} frames, comparing the common part", left_a.len(), left_b.len());
+    }
+
+    let diffs = left_a.iter().zip(&left_b).map(|(a, b)| a - b)
+        .zip(right_a.iter().zip(&right_b).map(|(a, b)| a - b))
+        .map(|(l, r)| l.abs().max(r.abs()))
+        .collect::<Vec<_>>();
+    let Some(first) = diffs.iter().position(|&d| d > 0.0) else {
+        println!("identical");
+        return Ok(());
+    };
+
+    let peak = diffs.iter().copied().fold(0.0, f32::max);
+    let rms = (diffs.iter().map(|d| (d * d) as f64).sum::<f64>() / diffs.len() as f64).sqrt();
+    println!("first difference at {:.6}s (frame {first})", first as f64 / rate_a as f64);
+    println!("peak difference {peak:.6} ({:.1} dBFS), rms {rms:.6}", 20.0 * peak.log10());
+    Ok(())
+}
 
-    fn sample_rate(&mut self, _: &jack::Client, srate: jack::Frames) -> jack::Control {
-        println!("JACK: rate={}", srate);
-        jack::Control::Continue
+/// Times a reload's worth of serializing and deserializing a state with a
+/// `rows` x `len` wavetable in `osc0`, in each encoding.
+fn bench(lib: PathBuf, crossfade: f64, rows: usize, len: usize, runs: u32) -> Result<()> {
+    let default = Lib::load(&lib)?.default_state(Encoding::Json)
+        .ok_or_else(|| anyhow::anyhow!("failed to serialize the default state"))?;
+    let mut state: serde_json::Value = serde_json::from_slice(&default)?;
+    let data = (0..rows)
+        .map(|i| (0..len).map(|j| (2.0 * PI * j as f32 / len as f32).sin() as f64 * i as f64 / rows as f64).collect())
+        .collect::<Vec<Vec<f64>>>();
+    state["osc0"]["waveform"] = serde_json::json!({ "Raw": { "data": data } });
+    let state = serde_json::to_vec(&state)?;
+
+    println!("{rows}x{len} wavetable, best of {runs}");
+    for encoding in [Encoding::Json, Encoding::Binary] {
+        let program = Program::from_state(Lib::load(&lib)?, &state, 48_000).map_err(|e| e.0)?;
+        let (mut engine, mut handle) = engine::new(program, crossfade, encoding);
+
+        // stands in for the audio callback, which answers snapshots and picks up swaps
+        let quit = Arc::new(AtomicBool::new(false));
+        let audio = std::thread::spawn({
+            let quit = Arc::clone(&quit);
+            move || {
+                let (mut left, mut right) = (vec![0.0; 256], vec![0.0; 256]);
+                while !quit.load(Ordering::Relaxed) {
+                    engine.process(48_000, &mut left, &mut right);
+                    std::thread::sleep(Duration::from_secs_f64(256.0 / 48_000.0));
+                }
+            }
+        });
+
+        let (mut serialize, mut reload, mut size) = (Duration::MAX, Duration::MAX, 0);
+        let mut run = || -> Result<()> {
+            let start = Instant::now();
+            let bytes = handle.serialize().ok_or_else(|| anyhow::anyhow!("failed to serialize {encoding:?}"))?;
+            serialize = serialize.min(start.elapsed());
+            size = bytes.len();
+
+            let start = Instant::now();
+            handle.reload(&lib)?;
+            reload = reload.min(start.elapsed());
+
+            // let the audio thread pick up the swap and finish fading before the next reload
+            std::thread::sleep(Duration::from_secs_f64(crossfade) + Duration::from_millis(50));
+            handle.collect();
+            Ok(())
+        };
+        let result = (0..runs).try_for_each(|_| run());
+        quit.store(true, Ordering::Relaxed);
+        audio.join().map_err(|_| anyhow::anyhow!("audio thread panicked"))?;
+        handle.collect();
+        result?;
+
+        println!(
+            "{:>6}: {:>10} bytes, serialize {:>8.1?}, reload {:>8.1?}",
+            encoding.extension(), size, serialize, reload,
+        );
     }
+    Ok(())
+}
 
-    fn xrun(&mut self, _: &jack::Client) -> jack::Control {
-        println!("JACK: xrun");
-        jack::Control::Continue
+fn state(config: Config, args: cli::State) -> Result<()> {
+    match args {
+        cli::State::Dump { snapshot, out, format, lib } => {
+            if format == Encoding::Binary && out.is_none() {
+                anyhow::bail!("binary state has to be written to a file with --out");
+            }
+
+            let lib = Lib::load(lib.unwrap_or(config.library))?;
+            let mut program = match snapshot {
+                Some(path) => Program::from_state(lib, &std::fs::read(path)?, 48_000).map_err(|e| e.0)?,
+                None => Program::new(lib, 48_000)?,
+            };
+
+            let bytes = program.serialize(format).ok_or_else(|| anyhow::anyhow!("failed to serialize state"))?;
+            program.unload();
+            match out {
+                Some(path) => std::fs::write(path, bytes)?,
+                None => println!("{}", String::from_utf8_lossy(&bytes)),
+            }
+        },
+        cli::State::List => {
+            for path in Snapshots::new(&config.state.dir, config.state.keep).list()? {
+                println!("{}", path.display());
+            }
+        },
+        cli::State::Load { snapshot, lib } => {
+            let lib = Lib::load(lib.unwrap_or(config.library))?;
+            Program::from_state(lib, &std::fs::read(&snapshot)?, 48_000).map_err(|e| e.0)?.unload();
+            println!("loaded {}", snapshot.display());
+        },
+        cli::State::Check { dir, lib } => {
+            let lib = lib.unwrap_or(config.library);
+            let mut fixtures = std::fs::read_dir(&dir)?
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| p.extension().is_some_and(|e| e == "json"))
+                .collect::<Vec<_>>();
+            fixtures.sort();
+
+            let mut failed = 0;
+            for path in &fixtures {
+                let state = std::fs::read(path)?;
+                let lib = Lib::load(&lib)?;
+                let old = lib.migrate_state(&state).ok();
+                match Program::from_state(lib, &state, 48_000) {
+                    Ok(mut program) => {
+                        println!("ok {}", path.display());
+                        let report = program.serialize(Encoding::Json)
+                            .zip(old)
+                            .and_then(|(new, old)| diff::Report::new(&old, &new, None));
+                        if let Some(report) = report.filter(|r| !r.is_empty()) {
+                            print!("{report}");
+                        }
+                        program.unload();
+                    },
+                    Err(e) => {
+                        println!("FAILED {}: {}", path.display(), e.0);
+                        failed += 1;
+                    },
+                }
+            }
+            if failed > 0 {
+                anyhow::bail!("{failed} of {} fixtures failed to load", fixtures.len());
+            }
+        },
+        cli::State::Bench { rows, len, runs, lib } => {
+            let crossfade = config.crossfade();
+            bench(lib.unwrap_or(config.library), crossfade, rows, len, runs)?
+        },
     }
+    Ok(())
 }
@@ -1,8 +1,11 @@
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use anyhow::{Result, anyhow};
 use midir::{MidiInput, MidiInputConnection, MidiOutput, MidiOutputConnection};
 
+use crate::reactor::Waker;
+
 pub use launchpad_x::LaunchpadX;
 pub use launch_control_xl::LaunchControlXL;
 
@@ -17,97 +20,200 @@ pub trait Device: Default + Send + 'static {
 }
 
 
+/// A controller, matched by port name prefix.
+///
+/// Survives the device being unplugged: sends are dropped while it's gone, and
+/// `poll` rescans the ports every second to notice it leaving and coming back,
+/// running `Device::setup` again on every reconnect.
 pub struct Midi<D: Device> {
+    name: String,
     inner: Arc<Mutex<MidiInner<D>>>,
-    in_conn: MidiInputConnection<()>,
-    out_conn: MidiOutputConnection,
+    conn: Option<(MidiInputConnection<()>, MidiOutputConnection)>,
+    waker: Waker,
+    next_scan: Instant,
 }
 
 struct MidiInner<D: Device> {
     device: D,
-    inputs: Vec<D::Input>,
+    clock: Clock,
+    inputs: Vec<(Instant, D::Input)>,
     outputs: Vec<D::Output>,
 }
 
+/// Maps midir's timestamps, microseconds from some backend specific origin, to `Instant`s.
+///
+/// The callback runs some unknown time after a message arrived, so the origin is
+/// estimated from the message that was delivered the quickest.
+#[derive(Default)]
+struct Clock {
+    origin: Option<Instant>,
+}
+
+impl Clock {
+    fn at(&mut self, stamp: u64) -> Instant {
+        let now = Instant::now();
+        // not every backend fills them in
+        if stamp == 0 {
+            return now;
+        }
+
+        let stamp = Duration::from_micros(stamp);
+        if let Some(origin) = now.checked_sub(stamp) {
+            if self.origin.is_none_or(|o| origin < o) {
+                self.origin = Some(origin);
+            }
+        }
+        self.origin.map_or(now, |o| (o + stamp).min(now))
+    }
+}
+
 impl<D: Device> Midi<D> {
-    pub fn list() -> Result<()> {
+    const SCAN_INTERVAL: Duration = Duration::from_secs(1);
+
+    /// Names of all MIDI input and output ports.
+    pub fn list() -> Result<(Vec<String>, Vec<String>)> {
         let midi_in = MidiInput::new("synth")?;
-        for port in midi_in.ports() {
-            println!("IN: '{:?}'", midi_in.port_name(&port).unwrap());
-        }
+        let inputs = midi_in.ports().iter()
+            .filter_map(|p| midi_in.port_name(p).ok())
+            .collect();
 
         let midi_out = MidiOutput::new("synth")?;
-        for port in midi_out.ports() {
-            println!("OUT: '{:?}'", midi_out.port_name(&port).unwrap());
-        }
+        let outputs = midi_out.ports().iter()
+            .filter_map(|p| midi_out.port_name(p).ok())
+            .collect();
 
-        Ok(())
+        Ok((inputs, outputs))
     }
 
-    pub fn open(name: &str) -> Result<Self> {
+    /// Opens the ports starting with `name`, waking `waker` whenever there's new input.
+    /// If they aren't there yet, they're connected once they show up.
+    pub fn open(name: &str, waker: Waker) -> Self {
         let inner = Arc::new(Mutex::<MidiInner<D>>::new(MidiInner {
             device: D::default(),
+            clock: Clock::default(),
             inputs: Vec::new(),
             outputs: Vec::new(),
         }));
 
+        let mut this = Self {
+            name: name.to_string(),
+            inner,
+            conn: None,
+            waker,
+            next_scan: Instant::now() + Self::SCAN_INTERVAL,
+        };
+        if let Err(e) = this.connect() {
+            println!("{e}\nwaiting for it to be connected");
+        }
+        this
+    }
+
+    pub fn connected(&self) -> bool {
+        self.conn.is_some()
+    }
+
+    fn connect(&mut self) -> Result<()> {
+        let name = &self.name;
         let midi_in = MidiInput::new(&format!("synth_in_{}", name))?;
         let midi_out = MidiOutput::new(&format!("synth_out_{}", name))?;
 
         let in_port = midi_in
             .ports().into_iter()
-            .find(|p| midi_in.port_name(p).unwrap().starts_with(name))
-            .ok_or_else(|| anyhow!("input not found: {name}"))?;
-        // let in_name = midi_in.port_name(&in_port).unwrap();
+            .find(|p| midi_in.port_name(p).is_ok_and(|n| n.starts_with(name)))
+            .ok_or_else(|| not_found("input", name, Self::list().map(|l| l.0)))?;
 
         let out_port = midi_out
             .ports().into_iter()
-            .find(|p| midi_out.port_name(p).unwrap().starts_with(name))
-            .ok_or_else(|| anyhow!("output not found: {name}"))?;
-        // let out_name = midi_out.port_name(&out_port).unwrap();
+            .find(|p| midi_out.port_name(p).is_ok_and(|n| n.starts_with(name)))
+            .ok_or_else(|| not_found("output", name, Self::list().map(|l| l.1)))?;
 
         let out_conn = midi_out
             .connect(&out_port, "out")
             .map_err(|_| anyhow!("failed to create output port"))?;
 
-        let _inner = Arc::clone(&inner);
+        // each connection gets a new client whose timestamps start over
+        self.inner.lock().unwrap().clock = Clock::default();
+
+        let _inner = Arc::clone(&self.inner);
+        let waker = self.waker.clone();
         let in_conn = midi_in
             .connect(
                 &in_port,
                 "in",
-                move |_, data, _| {
+                move |stamp, data, _| {
                     let mut inner = _inner.lock().unwrap();
+                    let at = inner.clock.at(stamp);
                     if let Some(input) = inner.device.process_input(data) {
-                        inner.inputs.push(input);
+                        inner.inputs.push((at, input));
+                        waker.wake();
                     }
                 },
                 (),
             )
             .map_err(|_| anyhow!("failed to create input port"))?;
 
-        let mut this = Self {
-            inner,
-            in_conn,
-            out_conn,
-        };
-        D::setup(&mut this);
+        self.conn = Some((in_conn, out_conn));
+        D::setup(self);
+        Ok(())
+    }
 
-        Ok(this)
+    fn disconnect(&mut self) {
+        if let Some((in_conn, out_conn)) = self.conn.take() {
+            in_conn.close();
+            out_conn.close();
+            println!("MIDI device '{}' disconnected", self.name);
+        }
     }
 
+    /// Rescans the ports if it's time to. Returns whether the device was connected or disconnected.
+    pub fn poll(&mut self) -> bool {
+        let now = Instant::now();
+        if now < self.next_scan {
+            return false;
+        }
+        self.next_scan = now + Self::SCAN_INTERVAL;
+
+        match self.connected() {
+            true => {
+                let present = Self::list().is_ok_and(|(inputs, outputs)| {
+                    inputs.iter().any(|p| p.starts_with(&self.name)) && outputs.iter().any(|p| p.starts_with(&self.name))
+                });
+                if !present {
+                    self.disconnect();
+                }
+                !present
+            },
+            false => {
+                let connected = self.connect().is_ok();
+                if connected {
+                    println!("MIDI device '{}' connected", self.name);
+                }
+                connected
+            },
+        }
+    }
+
+    /// Sends a frame to the device, or drops it if it isn't connected.
     pub fn send(&mut self, output: D::Output) {
-        let mut inner = self.inner.lock().unwrap();
-        let data = inner.device.process_output(output);
+        if !self.connected() {
+            return;
+        }
+        let data = self.inner.lock().unwrap().device.process_output(output);
         for frame in data {
-            self.out_conn.send(&frame).unwrap();
+            self.send_raw(&frame);
         }
     }
 
     pub fn send_raw(&mut self, data: &[u8]) {
-        self.out_conn.send(data).unwrap();
+        let Some((_, out_conn)) = &mut self.conn else { return };
+        if let Err(e) = out_conn.send(data) {
+            println!("MIDI send to '{}' failed: {e}", self.name);
+            self.disconnect();
+        }
     }
 
-    pub fn recv(&mut self) -> impl Iterator<Item = D::Input> + '_ {
+    /// Inputs received since the last call, with when they arrived.
+    pub fn recv(&mut self) -> impl Iterator<Item = (Instant, D::Input)> + '_ {
         self.inner.lock().unwrap()
             .inputs.drain(..)
             .collect::<Vec<_>>()
@@ -115,6 +221,19 @@ impl<D: Device> Midi<D> {
     }
 }
 
+fn not_found(kind: &str, name: &str, available: Result<Vec<String>>) -> anyhow::Error {
+    let available = available.unwrap_or_default();
+
+    let mut msg = format!("MIDI {kind} not found: '{name}'\navailable {kind}s:");
+    for port in &available {
+        msg += &format!("\n  '{port}'");
+    }
+    if available.is_empty() {
+        msg += " none";
+    }
+    anyhow!(msg)
+}
+
 mod launchpad_x {
     use types::launchpad_x::*;
 
@@ -157,9 +276,10 @@ mod launchpad_x {
 
         fn process_input(&mut self, raw: &[u8]) -> Option<Input> {
             Some(match raw[0] {
-                0x90 => (byte_to_pos(raw[1]), float(raw[2])),
-                0xA0 => (byte_to_pos(raw[1]), float(raw[2])),
-                0xB0 => (byte_to_pos(raw[1]), float(raw[2])),
+                0x90 | 0xA0 | 0xB0 => {
+                    let (x, y) = byte_to_pos(raw[1]);
+                    Input { x, y, v: float(raw[2]) }
+                },
                 _ => return None
             })
         }
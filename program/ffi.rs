@@ -1,55 +1,163 @@
+use std::panic::{AssertUnwindSafe, catch_unwind};
+
+use types::ffi::{Abi, Bytes, Devices, Encoding, Status, Transport};
+use types::launchpad_x as lpx;
+use types::launch_control_xl as lcx;
+
 use super::Program;
+use crate::state;
 
 const fn assert_send<T: Send>() {}
 const _: () = assert_send::<Program>();
 
+/// Runs `f`, catching any panic so it never unwinds into the host.
+fn guard(f: impl FnOnce() -> Status) -> Status {
+    catch_unwind(AssertUnwindSafe(f)).unwrap_or(Status::Panic)
+}
+
+fn program<'a>(this: *mut ()) -> &'a mut Program {
+    unsafe { &mut *(this as *mut Program) }
+}
+
+#[no_mangle]
+pub extern "C" fn abi() -> Abi {
+    Abi::current()
+}
+
+#[no_mangle]
+pub extern "C" fn process(this: *mut (), transport: &Transport, left: *mut f32, right: *mut f32, len: usize) -> Status {
+    guard(|| {
+        let left = unsafe { std::slice::from_raw_parts_mut(left, len) };
+        let right = unsafe { std::slice::from_raw_parts_mut(right, len) };
+        program(this).process(transport, left, right);
+        Status::Ok
+    })
+}
+
 #[no_mangle]
-pub fn sample(this: *mut (), t: f64) -> (f64, f64) {
-    let this = unsafe { &mut *(this as *mut Program) };
-    this.sample(t)
+pub extern "C" fn set_rate(this: *mut (), rate: f64) -> Status {
+    guard(|| {
+        program(this).set_rate(rate);
+        Status::Ok
+    })
 }
 
 #[no_mangle]
-pub fn pad_in(this: *mut (), t: f64, input: types::launchpad_x::Input) {
-    let this = unsafe { &mut *(this as *mut Program) };
-    this.pad_in(t, input)
+pub extern "C" fn set_devices(this: *mut (), devices: Devices) -> Status {
+    guard(|| {
+        program(this).set_devices(devices);
+        Status::Ok
+    })
 }
+
+#[no_mangle]
+pub extern "C" fn pad_in(this: *mut (), transport: &Transport, input: lpx::Input) -> Status {
+    guard(|| {
+        program(this).pad_in(transport, input);
+        Status::Ok
+    })
+}
+#[no_mangle]
+pub extern "C" fn pad_out(this: *mut (), transport: &Transport, out: &mut lpx::Output) -> Status {
+    guard(|| {
+        *out = program(this).pad_out(transport);
+        Status::Ok
+    })
+}
+
 #[no_mangle]
-pub fn pad_out(this: *mut (), t: f64) -> types::launchpad_x::Output {
-    let this = unsafe { &mut *(this as *mut Program) };
-    this.pad_out(t)
+pub extern "C" fn ctrl_in(this: *mut (), transport: &Transport, input: lcx::Input) -> Status {
+    guard(|| {
+        program(this).ctrl_in(transport, input);
+        Status::Ok
+    })
+}
+#[no_mangle]
+pub extern "C" fn ctrl_out(this: *mut (), transport: &Transport, out: &mut lcx::Output) -> Status {
+    guard(|| {
+        *out = program(this).ctrl_out(transport);
+        Status::Ok
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn default(out: &mut *mut ()) -> Status {
+    guard(|| {
+        *out = Box::into_raw(Box::new(Program::default())) as *mut ();
+        Status::Ok
+    })
 }
 
+/// A new instance with a copy of the state, so it can be serialized while `this` keeps playing.
 #[no_mangle]
-pub fn ctrl_in(this: *mut (), t: f64, input: types::launch_control_xl::Input) {
-    let this = unsafe { &mut *(this as *mut Program) };
-    this.ctrl_in(t, input)
+pub extern "C" fn clone_state(this: *mut (), out: &mut *mut ()) -> Status {
+    guard(|| {
+        *out = Box::into_raw(Box::new(program(this).clone())) as *mut ();
+        Status::Ok
+    })
 }
+
+/// On `Status::Error`, `out` holds the message instead of the state.
 #[no_mangle]
-pub fn ctrl_out(this: *mut (), t: f64) -> types::launch_control_xl::Output {
-    let this = unsafe { &mut *(this as *mut Program) };
-    this.ctrl_out(t)
+pub extern "C" fn serialize(this: *mut (), encoding: Encoding, out: &mut Bytes) -> Status {
+    guard(|| match state::serialize(program(this), encoding) {
+        Ok(bytes) => {
+            *out = Bytes::from_vec(bytes);
+            Status::Ok
+        },
+        Err(e) => {
+            *out = Bytes::from_vec(e.into_bytes());
+            Status::Error
+        },
+    })
 }
 
+/// On `Status::Error`, `error` holds the message.
 #[no_mangle]
-pub fn default() -> *mut () {
-    let this = Program::default();
-    Box::into_raw(Box::new(this)) as *mut ()
+pub extern "C" fn deserialize(ptr: *const u8, len: usize, out: &mut *mut (), error: &mut Bytes) -> Status {
+    guard(|| {
+        let bytes = unsafe { std::slice::from_raw_parts(ptr, len) };
+        match state::deserialize(bytes) {
+            Ok(state) => {
+                *out = Box::into_raw(Box::new(state)) as *mut ();
+                Status::Ok
+            },
+            Err(e) => {
+                *error = Bytes::from_vec(e.into_bytes());
+                Status::Error
+            },
+        }
+    })
 }
 
+/// State in either encoding as JSON, migrated to this build's version.
+/// On `Status::Error`, `out` holds the message instead.
 #[no_mangle]
-pub fn serialize(this: *mut ()) -> Vec<u8> {
-    let state: &Program = unsafe { &*(this as *mut Program) };
-    serde_json::to_vec(state).unwrap()
+pub extern "C" fn migrate_state(ptr: *const u8, len: usize, out: &mut Bytes) -> Status {
+    guard(|| {
+        let bytes = unsafe { std::slice::from_raw_parts(ptr, len) };
+        match state::migrated(bytes) {
+            Ok(json) => {
+                *out = Bytes::from_vec(json);
+                Status::Ok
+            },
+            Err(e) => {
+                *out = Bytes::from_vec(e.into_bytes());
+                Status::Error
+            },
+        }
+    })
 }
 
 #[no_mangle]
-pub fn deserialize(bytes: &[u8]) -> Result<*mut (), Box<dyn std::error::Error>> {
-    let state: Program = serde_json::from_reader(bytes)?;
-    Ok(Box::into_raw(Box::new(state)) as *mut ())
+pub extern "C" fn free_bytes(bytes: Bytes) {
+    let _ = unsafe { bytes.into_vec() };
 }
 
 #[no_mangle]
-pub fn drop(this: *mut ()) {
-    let _ = unsafe { Box::from_raw(this as *mut Program) };
+pub extern "C" fn drop(this: *mut ()) -> Status {
+    guard(|| {
+        let _ = unsafe { Box::from_raw(this as *mut Program) };
+        Status::Ok
+    })
 }
@@ -6,10 +6,14 @@ use std::default::default;
 use std::f64::consts::PI;
 
 use logic::synth;
+use types::ffi::{Devices, Transport};
 use types::launchpad_x as lpx;
 use types::launch_control_xl as lcx;
 
 mod ffi;
+mod migrate;
+mod msgpack;
+mod state;
 
 mod utils;
 use utils::*;
@@ -19,7 +23,7 @@ use crate::utils::math::Float;
 
 mod logic;
 
-#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
 #[serde(default)]
 pub struct Program {
     piano: logic::pad::Piano,
@@ -29,6 +33,8 @@ pub struct Program {
     volume: f64,
 
     t: f64,
+    #[serde(skip)]
+    rate: f64,
 }
 
 impl Default for Program {
@@ -50,30 +56,41 @@ impl Default for Program {
             volume: 0.01,
 
             t: 0.0,
+            rate: 48_000.0,
         }
     }
 }
 
 impl Program {
-    pub fn pad_in(&mut self, t: f64, input: lpx::Input) {
-        self.piano.pad_in(t, input);
+    pub fn set_rate(&mut self, rate: f64) {
+        self.rate = rate;
+    }
+
+    pub fn set_devices(&mut self, devices: Devices) {
+        // nothing will ever send the note offs
+        if !devices.pad {
+            self.piano.release();
+        }
+    }
 
-        let ((x, y), v) = input;
+    pub fn pad_in(&mut self, transport: &Transport, input: lpx::Input) {
+        self.piano.pad_in(transport, input);
+
+        let lpx::Input { x, y, v } = input;
         match (x, y) {
             (7, 8) if v > 0.0 => *self = Self::default(),
             _ => {},
             _ => {},
         }
     }
-    pub fn pad_out(&mut self, t: f64) -> lpx::Output {
+    pub fn pad_out(&mut self, transport: &Transport) -> lpx::Output {
         let mut output = lpx::clear();
-        self.piano.pad_out(t, &mut output);
+        self.piano.pad_out(transport, &mut output);
         output
     }
 
-    pub fn ctrl_in(&mut self, t: f64, input: lcx::Input) {
+    pub fn ctrl_in(&mut self, transport: &Transport, input: lcx::Input) {
         use lcx::Input;
-        println!("{input:?}");
         match input {
             Input::Knob(x, y, f) => match (x, y) {
                 (0, 0) => self.osc0.tune = f,
@@ -100,23 +117,30 @@ impl Program {
             _ => {},
         }
     }
-    pub fn ctrl_out(&mut self, t: f64) -> lcx::Output { lcx::clear() }
+    pub fn ctrl_out(&mut self, transport: &Transport) -> lcx::Output { lcx::clear() }
 
-    pub fn sample(&mut self, t: f64) -> (f64, f64) {
-        let dt = t - self.t;
-        self.t = t;
+    pub fn sample(&mut self, transport: &Transport) -> (f64, f64) {
+        self.t = transport.seconds;
 
         let mut f = 0.0;
 
         for (note, v) in self.piano.notes() {
-            f += self.osc0.sample(dt, note) * 1.0;
-            f += self.osc1.sample(dt, note) * 1.0;
-            f += self.osc2.sample(dt, note) * 1.0;
+            f += self.osc0.sample(transport, note) * 1.0;
+            f += self.osc1.sample(transport, note) * 1.0;
+            f += self.osc2.sample(transport, note) * 1.0;
         }
 
         f *= self.volume;
         (f, f)
     }
 
+    pub fn process(&mut self, transport: &Transport, left: &mut [f32], right: &mut [f32]) {
+        for (i, frame) in left.iter_mut().zip(right.iter_mut()).enumerate() {
+            let (l, r) = self.sample(&transport.offset(i as u64));
+            *frame.0 = l as f32;
+            *frame.1 = r as f32;
+        }
+    }
+
     pub fn tick(&mut self, t: f64) {}
 }
@@ -1,5 +1,6 @@
 use std::default::default;
 
+use types::ffi::Transport;
 use types::launchpad_x as lpx;
 
 use crate::utils::color::Color;
@@ -33,7 +34,11 @@ impl Piano {
             .filter(|(_, v)| *v > 0.0)
     }
 
-    pub fn pad_in(&mut self, _t: f64, ((x, mut y), fr): lpx::Input) {
+    pub fn release(&mut self) {
+        self.notes = default();
+    }
+
+    pub fn pad_in(&mut self, _transport: &Transport, lpx::Input { x, mut y, v: fr }: lpx::Input) {
         y -= self.y;
         match (x, y) {
             (0, 0) => self.notes[0] = fr,
@@ -53,7 +58,7 @@ impl Piano {
         }
     }
 
-    pub fn pad_out(&self, _t: f64, output: &mut lpx::Output) {
+    pub fn pad_out(&self, _transport: &Transport, output: &mut lpx::Output) {
         for (i, &v) in self.notes.iter().enumerate() {
             let (x, mut y) = match i {
                 0 => (0, 0),
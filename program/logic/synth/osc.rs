@@ -1,9 +1,11 @@
 use std::default::default;
 
+use types::ffi::Transport;
+
 use crate::utils::sound::Note;
 use crate::utils::synth::Waveform;
 
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct Osc {
     pub waveform: Waveform,
 
@@ -27,9 +29,9 @@ impl Default for Osc {
 }
 
 impl Osc {
-    pub fn sample(&mut self, dt: f64, note: Note) -> f64 {
+    pub fn sample(&mut self, transport: &Transport, note: Note) -> f64 {
         let freq = note.detune(self.tune).freq();
-        self.phi += dt * freq;
+        self.phi += transport.dt() * freq;
 
         self.waveform.sample(self.phi + self.phase) * self.amp
     }
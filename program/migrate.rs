@@ -0,0 +1,162 @@
+use serde_json::{Map, Value};
+
+/// Version written into serialized state. Bump it by appending to `MIGRATIONS`,
+/// and add a fixture for the new version to `fixtures/` and the tests below.
+pub const VERSION: u64 = MIGRATIONS.len() as u64;
+
+/// Upgrades serialized state by one version, e.g. when a field is renamed or
+/// changes type. `MIGRATIONS[n]` takes state from version `n` to `n + 1`.
+/// State from before versioning has no tag and counts as version 0.
+#[derive(Copy, Clone)]
+pub struct Migration {
+    pub name: &'static str,
+    pub apply: fn(&mut Value),
+}
+
+pub const MIGRATIONS: &[Migration] = &[
+    // only the tag changed, but snapshots tagged version 1 are already around
+    // and a build that knew no versions would refuse them as too new
+    Migration { name: "add version tag", apply: |_| {} },
+];
+
+/// Tags freshly serialized state with the current version.
+pub fn tag(state: &mut Value) {
+    if let Some(obj) = state.as_object_mut() {
+        obj.insert("version".into(), VERSION.into());
+    }
+}
+
+/// Upgrades `state` from whatever version it's tagged with through the rest
+/// of `migrations`, usually `MIGRATIONS`, removing the tag.
+pub fn migrate(state: &mut Value, migrations: &[Migration]) -> Result<(), String> {
+    let obj = state.as_object_mut().ok_or("state isn't an object")?;
+    let version = match obj.remove("version") {
+        None => 0,
+        Some(v) => v.as_u64().ok_or_else(|| format!("invalid state version {v}"))?,
+    };
+    let latest = migrations.len() as u64;
+    if version > latest {
+        return Err(format!("state is version {version}, but this build only knows up to {latest}"));
+    }
+
+    for migration in &migrations[version as usize..] {
+        (migration.apply)(state);
+    }
+    Ok(())
+}
+
+/// The object at `path`, if there is one.
+pub fn object<'a>(state: &'a mut Value, path: &[&str]) -> Option<&'a mut Map<String, Value>> {
+    path.iter()
+        .try_fold(state, |v, key| v.get_mut(*key))
+        .and_then(Value::as_object_mut)
+}
+
+/// Renames field `from` of the object at `path` to `to`.
+pub fn rename(state: &mut Value, path: &[&str], from: &str, to: &str) {
+    if let Some(obj) = object(state, path) {
+        if let Some(v) = obj.remove(from) {
+            obj.insert(to.into(), v);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use types::ffi::Encoding;
+
+    use super::*;
+    use crate::{Program, state};
+
+    /// A played state saved by each version, `FIXTURES[n]` by version `n`.
+    /// Adding a migration without a fixture for it doesn't compile.
+    const FIXTURES: [&str; VERSION as usize + 1] = [
+        include_str!("fixtures/v0.json"),
+        include_str!("fixtures/v1.json"),
+    ];
+
+    /// `MIGRATIONS` plus one that really changes the schema, to check a chain
+    /// of them applies in order without renaming anything in `Program`.
+    fn chain() -> Vec<Migration> {
+        let rename_tune = Migration {
+            name: "rename osc tune to detune",
+            apply: |state| {
+                for osc in ["osc0", "osc1", "osc2"] {
+                    rename(state, &[osc], "tune", "detune");
+                }
+            },
+        };
+        MIGRATIONS.iter().copied().chain([rename_tune]).collect()
+    }
+
+    /// Saved by a build with `chain()`.
+    const NEXT: &str = include_str!("fixtures/test/v2.json");
+
+    fn load(version: usize) -> Program {
+        state::deserialize(FIXTURES[version].as_bytes()).unwrap_or_else(|e| panic!("v{version}: {e}"))
+    }
+
+    fn notes(p: &Program) -> Vec<f64> {
+        p.piano.notes().map(|(_, v)| v).collect()
+    }
+
+    /// `fixture` migrated through `chain()`, with the detune of each osc.
+    fn detune(fixture: &str) -> [f64; 3] {
+        let mut state: Value = serde_json::from_str(fixture).unwrap();
+        migrate(&mut state, &chain()).unwrap();
+        ["osc0", "osc1", "osc2"].map(|osc| {
+            assert!(state[osc].get("tune").is_none(), "{osc}.tune wasn't renamed");
+            state[osc]["detune"].as_f64().unwrap()
+        })
+    }
+
+    #[test]
+    fn fixtures_are_tagged() {
+        for (version, fixture) in FIXTURES.iter().chain([&NEXT]).enumerate() {
+            let state: Value = serde_json::from_str(fixture).unwrap();
+            let tag = state.get("version").and_then(Value::as_u64).unwrap_or(0);
+            assert_eq!(tag, version as u64);
+        }
+    }
+
+    #[test]
+    fn v0() {
+        let p = load(0);
+        assert_eq!(notes(&p), [0.5]);
+        assert_eq!(p.osc1.tune, 7.0);
+        assert_eq!(p.osc2.tune, -12.0);
+        assert_eq!(p.volume, 0.02);
+    }
+
+    #[test]
+    fn v1() {
+        let p = load(1);
+        assert_eq!(notes(&p), [1.0, 0.25]);
+        assert_eq!(p.osc0.tune, 0.5);
+        assert_eq!(p.osc2.tune, 12.0);
+        assert_eq!(p.volume, 0.05);
+    }
+
+    #[test]
+    fn chain_applies_in_order() {
+        assert_eq!(detune(FIXTURES[0]), [0.0, 7.0, -12.0]);
+        assert_eq!(detune(FIXTURES[1]), [0.5, 0.0, 12.0]);
+        assert_eq!(detune(NEXT), [0.0, -5.0, 0.1]);
+    }
+
+    #[test]
+    fn roundtrip() {
+        let p = load(VERSION as usize);
+        let expected = serde_json::to_value(&p).unwrap();
+        for encoding in [Encoding::Json, Encoding::Binary] {
+            let state = state::serialize(&p, encoding).unwrap();
+            let p = state::deserialize(&state).unwrap();
+            assert_eq!(serde_json::to_value(&p).unwrap(), expected, "{encoding:?}");
+        }
+    }
+
+    #[test]
+    fn newer_version_is_refused() {
+        assert!(state::deserialize(NEXT.as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,197 @@
+use serde_json::{Map, Number, Value};
+
+/// MessagePack encoding of a JSON value, for binary state. It keeps field
+/// names, so it can be migrated like JSON and survives fields being added or
+/// reordered between builds, but numbers aren't formatted or parsed as text,
+/// which is most of the time spent on large states.
+pub fn encode(value: &Value, out: &mut Vec<u8>) {
+    match value {
+        Value::Null => out.push(0xc0),
+        Value::Bool(b) => out.push(if *b { 0xc3 } else { 0xc2 }),
+        Value::Number(n) => {
+            if let Some(u) = n.as_u64() {
+                match u {
+                    0..=0x7f => out.push(u as u8),
+                    _ => {
+                        out.push(0xcf);
+                        out.extend_from_slice(&u.to_be_bytes());
+                    },
+                }
+            } else if let Some(i) = n.as_i64() {
+                match i {
+                    -32..=-1 => out.push(i as u8),
+                    _ => {
+                        out.push(0xd3);
+                        out.extend_from_slice(&i.to_be_bytes());
+                    },
+                }
+            } else {
+                out.push(0xcb);
+                out.extend_from_slice(&n.as_f64().unwrap_or_default().to_be_bytes());
+            }
+        },
+        Value::String(s) => {
+            header(out, s.len(), 0xa0, 32, 0xdb);
+            out.extend_from_slice(s.as_bytes());
+        },
+        Value::Array(items) => {
+            header(out, items.len(), 0x90, 16, 0xdd);
+            for item in items {
+                encode(item, out);
+            }
+        },
+        Value::Object(fields) => {
+            header(out, fields.len(), 0x80, 16, 0xdf);
+            for (key, value) in fields {
+                header(out, key.len(), 0xa0, 32, 0xdb);
+                out.extend_from_slice(key.as_bytes());
+                encode(value, out);
+            }
+        },
+    }
+}
+
+/// The fixed size form if `len` fits, otherwise the 32 bit one.
+fn header(out: &mut Vec<u8>, len: usize, fixed: u8, max: usize, long: u8) {
+    if len < max {
+        out.push(fixed | len as u8);
+    } else {
+        out.push(long);
+        out.extend_from_slice(&(len as u32).to_be_bytes());
+    }
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Value, String> {
+    let mut reader = Reader { bytes, pos: 0 };
+    let value = reader.value()?;
+    if reader.pos != bytes.len() {
+        return Err(format!("{} trailing bytes after the state", bytes.len() - reader.pos));
+    }
+    Ok(value)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let end = self.pos.checked_add(n).filter(|&end| end <= self.bytes.len())
+            .ok_or("truncated binary state")?;
+        let bytes = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn len(&mut self, width: usize) -> Result<usize, String> {
+        Ok(match width {
+            1 => self.array::<1>()?[0] as usize,
+            2 => u16::from_be_bytes(self.array()?) as usize,
+            _ => u32::from_be_bytes(self.array()?) as usize,
+        })
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        let tag = self.array::<1>()?[0];
+        Ok(match tag {
+            0x00..=0x7f => tag.into(),
+            0x80..=0x8f => self.map((tag & 0x0f) as usize)?,
+            0x90..=0x9f => self.seq((tag & 0x0f) as usize)?,
+            0xa0..=0xbf => self.str((tag & 0x1f) as usize)?.into(),
+            0xc0 => Value::Null,
+            0xc2 => false.into(),
+            0xc3 => true.into(),
+            0xca => float(f32::from_be_bytes(self.array()?) as f64),
+            0xcb => float(f64::from_be_bytes(self.array()?)),
+            0xcc => self.array::<1>()?[0].into(),
+            0xcd => u16::from_be_bytes(self.array()?).into(),
+            0xce => u32::from_be_bytes(self.array()?).into(),
+            0xcf => u64::from_be_bytes(self.array()?).into(),
+            0xd0 => (self.array::<1>()?[0] as i8).into(),
+            0xd1 => i16::from_be_bytes(self.array()?).into(),
+            0xd2 => i32::from_be_bytes(self.array()?).into(),
+            0xd3 => i64::from_be_bytes(self.array()?).into(),
+            0xd9..=0xdb => {
+                let len = self.len(1 << (tag - 0xd9))?;
+                self.str(len)?.into()
+            },
+            0xdc | 0xdd => {
+                let len = self.len(if tag == 0xdc { 2 } else { 4 })?;
+                self.seq(len)?
+            },
+            0xde | 0xdf => {
+                let len = self.len(if tag == 0xde { 2 } else { 4 })?;
+                self.map(len)?
+            },
+            0xe0..=0xff => (tag as i8).into(),
+            _ => return Err(format!("unsupported MessagePack type {tag:#04x} at byte {}", self.pos - 1)),
+        })
+    }
+
+    fn str(&mut self, len: usize) -> Result<String, String> {
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|e| e.to_string())
+    }
+
+    fn seq(&mut self, len: usize) -> Result<Value, String> {
+        // every item takes at least a byte, so a bogus length can't allocate much
+        let mut items = Vec::with_capacity(len.min(self.bytes.len() - self.pos));
+        for _ in 0..len {
+            items.push(self.value()?);
+        }
+        Ok(Value::Array(items))
+    }
+
+    fn map(&mut self, len: usize) -> Result<Value, String> {
+        let mut fields = Map::new();
+        for _ in 0..len {
+            let key = match self.value()? {
+                Value::String(key) => key,
+                key => return Err(format!("field names have to be strings, not {key}")),
+            };
+            fields.insert(key, self.value()?);
+        }
+        Ok(Value::Object(fields))
+    }
+}
+
+fn float(f: f64) -> Value {
+    Number::from_f64(f).map_or(Value::Null, Value::Number)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let long = "x".repeat(40);
+        let value = json!({
+            "ints": [0, 127, 128, u64::MAX, -1, -32, -33, i64::MIN],
+            "floats": [0.5, -1.25, 1e300],
+            "strings": ["", "short", long],
+            "nested": { "null": null, "bools": [true, false], "empty": {} },
+            "wide": (0..20).collect::<Vec<_>>(),
+        });
+
+        let mut bytes = Vec::new();
+        encode(&value, &mut bytes);
+        assert_eq!(decode(&bytes).unwrap(), value);
+    }
+
+    #[test]
+    fn truncated() {
+        let mut bytes = Vec::new();
+        encode(&json!({ "a": [1.0, 2.0] }), &mut bytes);
+        for len in 0..bytes.len() {
+            assert!(decode(&bytes[..len]).is_err());
+        }
+    }
+}
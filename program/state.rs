@@ -0,0 +1,45 @@
+use serde_json::Value;
+use types::ffi::{BINARY_MAGIC, Encoding};
+
+use crate::Program;
+use crate::{migrate, msgpack};
+
+/// Serialized state, tagged with the current schema version.
+///
+/// Binary state is `BINARY_MAGIC` followed by the same tagged value as JSON,
+/// encoded as MessagePack.
+pub fn serialize(p: &Program, encoding: Encoding) -> Result<Vec<u8>, String> {
+    let mut state = serde_json::to_value(p).map_err(|e| e.to_string())?;
+    migrate::tag(&mut state);
+    match encoding {
+        Encoding::Json => serde_json::to_vec(&state).map_err(|e| e.to_string()),
+        Encoding::Binary => {
+            let mut bytes = BINARY_MAGIC.to_vec();
+            msgpack::encode(&state, &mut bytes);
+            Ok(bytes)
+        },
+    }
+}
+
+/// Deserializes state in either encoding, migrating it from older versions.
+pub fn deserialize(bytes: &[u8]) -> Result<Program, String> {
+    let mut state = parse(bytes)?;
+    migrate::migrate(&mut state, migrate::MIGRATIONS)?;
+    serde_json::from_value(state).map_err(|e| e.to_string())
+}
+
+/// State in either encoding, migrated to the current version and re-encoded
+/// as untagged JSON, so the host can compare it with what a new build serializes.
+pub fn migrated(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    let mut state = parse(bytes)?;
+    migrate::migrate(&mut state, migrate::MIGRATIONS)?;
+    serde_json::to_vec(&state).map_err(|e| e.to_string())
+}
+
+/// The tagged value of a state in either encoding.
+pub fn parse(bytes: &[u8]) -> Result<Value, String> {
+    match Encoding::detect(bytes) {
+        Encoding::Json => serde_json::from_slice(bytes).map_err(|e| e.to_string()),
+        Encoding::Binary => msgpack::decode(&bytes[BINARY_MAGIC.len()..]),
+    }
+}
@@ -12,8 +12,8 @@ impl Color {
     pub const fn white() -> Self   { Self(1.0, 1.0, 1.0) }
 }
 
-impl From<Color> for (f64, f64, f64) {
+impl From<Color> for types::launchpad_x::Rgb {
     fn from(c: Color) -> Self {
-        (c.0, c.1, c.2)
+        Self(c.0, c.1, c.2)
     }
 }
@@ -21,7 +21,7 @@ static SQUARE_TABLE: LazyLock<Wavetable> = LazyLock::new(|| {
     Wavetable::new_periodic(|t, _| if t - t.floor() < 0.5 { 1.0 } else { -1.0 })
 });
 
-#[derive(Default, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Clone, Default, Debug, serde::Serialize, serde::Deserialize)]
 pub enum Waveform {
     #[default]
     Sine,
@@ -53,7 +53,7 @@ impl Waveform {
     }
 }
 
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct Wavetable {
     data: Vec<Vec<f64>>,
 }
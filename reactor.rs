@@ -0,0 +1,39 @@
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::time::Instant;
+
+/// Lets the control loop sleep until one of its sources has something for it,
+/// instead of polling them all on a fixed interval.
+///
+/// MIDI callbacks, the file watchers and the build thread each hold a `Waker`.
+/// Wakes are coalesced, the loop checks every source once it's woken anyway.
+pub struct Reactor {
+    rx: Receiver<()>,
+    waker: Waker,
+}
+
+#[derive(Clone)]
+pub struct Waker(SyncSender<()>);
+
+impl Waker {
+    pub fn wake(&self) {
+        // full means a wake is already pending
+        let _ = self.0.try_send(());
+    }
+}
+
+impl Reactor {
+    pub fn new() -> Self {
+        let (tx, rx) = mpsc::sync_channel(1);
+        Self { rx, waker: Waker(tx) }
+    }
+
+    pub fn waker(&self) -> Waker {
+        self.waker.clone()
+    }
+
+    /// Blocks until woken or until `deadline`, whichever comes first.
+    pub fn wait(&self, deadline: Instant) {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        let _ = self.rx.recv_timeout(timeout);
+    }
+}
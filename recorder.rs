@@ -0,0 +1,214 @@
+use std::fmt::Write as _;
+use std::io::{Seek, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use rtrb::{Consumer, Producer, RingBuffer};
+
+use crate::wav;
+
+/// Read end of the ring buffer, handed back by the writer thread when it's done.
+type Frames = Consumer<(f32, f32)>;
+
+/// Records the live output to WAV files.
+///
+/// The audio thread copies each block into a ring buffer through its `Tap`,
+/// and a writer thread drains it to disk, so recording never blocks audio.
+/// Markers, e.g. for reloads, go into an Audacity label file next to the WAV.
+pub struct Recorder {
+    dir: PathBuf,
+    format: wav::Format,
+    shared: Arc<Shared>,
+    /// Handed to the writer thread while recording.
+    consumer: Option<Frames>,
+    /// Hands the consumer back even if writing failed, so the next start works.
+    writer: Option<JoinHandle<(Frames, Result<()>)>>,
+    stop: Arc<AtomicBool>,
+    labels: String,
+    path: Option<PathBuf>,
+}
+
+/// Audio thread half.
+pub struct Tap {
+    producer: Producer<(f32, f32)>,
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    recording: AtomicBool,
+    rate: AtomicU32,
+    /// Frames handed to the writer in the current recording.
+    frames: AtomicU64,
+    /// Frames the writer didn't keep up with.
+    dropped: AtomicU64,
+}
+
+pub fn new(dir: impl Into<PathBuf>, format: wav::Format) -> (Recorder, Tap) {
+    // a couple of seconds of slack for the disk
+    let (producer, consumer) = RingBuffer::new(192_000);
+    let shared = Arc::new(Shared {
+        recording: AtomicBool::new(false),
+        rate: AtomicU32::new(48_000),
+        frames: AtomicU64::new(0),
+        dropped: AtomicU64::new(0),
+    });
+
+    let recorder = Recorder {
+        dir: dir.into(),
+        format,
+        shared: Arc::clone(&shared),
+        consumer: Some(consumer),
+        writer: None,
+        stop: Arc::new(AtomicBool::new(false)),
+        labels: String::new(),
+        path: None,
+    };
+    (recorder, Tap { producer, shared })
+}
+
+impl Tap {
+    pub fn write(&mut self, rate: u32, left: &[f32], right: &[f32]) {
+        self.shared.rate.store(rate, Ordering::Relaxed);
+        if !self.shared.recording.load(Ordering::Acquire) {
+            return;
+        }
+
+        let mut written = 0;
+        for frame in left.iter().copied().zip(right.iter().copied()) {
+            if self.producer.push(frame).is_err() {
+                break;
+            }
+            written += 1;
+        }
+        self.shared.frames.fetch_add(written, Ordering::Relaxed);
+        self.shared.dropped.fetch_add(left.len() as u64 - written, Ordering::Relaxed);
+    }
+}
+
+impl Recorder {
+    pub fn recording(&self) -> bool {
+        self.writer.is_some()
+    }
+
+    pub fn toggle(&mut self) -> Result<()> {
+        match self.recording() {
+            true => self.stop(),
+            false => self.start(),
+        }
+    }
+
+    /// Starts a new recording named after the current time.
+    pub fn start(&mut self) -> Result<()> {
+        if self.recording() {
+            return Ok(());
+        }
+        let Some(mut consumer) = self.consumer.take() else {
+            anyhow::bail!("recorder is broken after the writer panicked");
+        };
+
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("failed to create {}", self.dir.display()))?;
+        let millis = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+        let path = self.dir.join(format!("{millis:013}.wav"));
+
+        let rate = self.shared.rate.load(Ordering::Relaxed);
+        let mut wav = match wav::Writer::create(&path, rate, self.format) {
+            Ok(wav) => wav,
+            Err(e) => {
+                self.consumer = Some(consumer);
+                return Err(e.context(format!("failed to create {}", path.display())));
+            },
+        };
+
+        // anything the audio thread pushed while the last recording was stopping
+        let stale = consumer.slots();
+        consumer.read_chunk(stale).unwrap().commit_all();
+
+        self.stop.store(false, Ordering::Relaxed);
+        self.shared.frames.store(0, Ordering::Relaxed);
+        self.shared.dropped.store(0, Ordering::Relaxed);
+        self.shared.recording.store(true, Ordering::Release);
+
+        let stop = Arc::clone(&self.stop);
+        let shared = Arc::clone(&self.shared);
+        self.writer = Some(std::thread::spawn(move || {
+            let result = drain(&mut consumer, &mut wav, &stop).and_then(|()| wav.finish().map(|_| ()));
+            if result.is_err() {
+                // nobody's reading anymore, until the next start
+                shared.recording.store(false, Ordering::Release);
+            }
+            (consumer, result)
+        }));
+
+        println!("recording to {}", path.display());
+        self.labels.clear();
+        self.path = Some(path);
+        Ok(())
+    }
+
+    pub fn stop(&mut self) -> Result<()> {
+        let Some(writer) = self.writer.take() else { return Ok(()) };
+
+        self.shared.recording.store(false, Ordering::Release);
+        self.stop.store(true, Ordering::Release);
+        let path = self.path.take().unwrap();
+        let (consumer, result) = writer.join().map_err(|_| anyhow::anyhow!("recording writer panicked"))?;
+        self.consumer = Some(consumer);
+
+        // the labels are still worth having for whatever made it to disk
+        if !self.labels.is_empty() {
+            std::fs::write(path.with_extension("txt"), &self.labels)?;
+        }
+        result.with_context(|| format!("failed to write {}", path.display()))?;
+
+        let dropped = self.shared.dropped.load(Ordering::Relaxed);
+        if dropped > 0 {
+            println!("recording dropped {dropped} frames, the disk didn't keep up");
+        }
+        println!("recorded {}", path.display());
+        Ok(())
+    }
+
+    /// Notes `label` at the current position of the recording, if there is one.
+    pub fn mark(&mut self, label: &str) {
+        if !self.recording() {
+            return;
+        }
+        let frames = self.shared.frames.load(Ordering::Relaxed);
+        let t = frames as f64 / self.shared.rate.load(Ordering::Relaxed) as f64;
+        let _ = writeln!(self.labels, "{t:.6}\t{t:.6}\t{label}");
+    }
+}
+
+/// Writes everything the audio thread pushes to `wav` until `stop` is set.
+fn drain<W: Write + Seek>(consumer: &mut Frames, wav: &mut wav::Writer<W>, stop: &AtomicBool) -> Result<()> {
+    loop {
+        // check before draining, so nothing pushed before the stop is lost
+        let stopping = stop.load(Ordering::Acquire);
+        let n = consumer.slots();
+        if n > 0 {
+            let chunk = consumer.read_chunk(n).unwrap();
+            let (a, b) = chunk.as_slices();
+            for &(l, r) in a.iter().chain(b) {
+                wav.write(l, r)?;
+            }
+            chunk.commit_all();
+        } else if stopping {
+            return Ok(());
+        } else {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if let Err(e) = self.stop() {
+            println!("failed to finish recording: {e}");
+        }
+    }
+}
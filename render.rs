@@ -0,0 +1,194 @@
+use std::fmt;
+use std::path::Path;
+
+use anyhow::{Context, Result, anyhow, bail};
+use types::ffi::Devices;
+use types::launchpad_x as lpx;
+use types::launch_control_xl as lcx;
+
+use crate::dylib::Program;
+use crate::engine::Input;
+use crate::wav;
+
+#[derive(Copy, Clone, Debug)]
+pub struct Event {
+    pub t: f64,
+    pub input: Input,
+}
+
+/// Formats the event as a script line, so anything written this way can be parsed back.
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let on = |b: bool| if b { "on" } else { "off" };
+        write!(f, "{:.6}  ", self.t)?;
+        match self.input {
+            Input::Pad(lpx::Input { x, y, v }) => write!(f, "pad {x} {y} {v}"),
+            Input::Ctrl(input) => match input {
+                lcx::Input::Knob(x, y, v) => write!(f, "ctrl knob {x} {y} {v}"),
+                lcx::Input::Slider(i, v) => write!(f, "ctrl slider {i} {v}"),
+                lcx::Input::Button(x, y, b) => write!(f, "ctrl button {x} {y} {}", on(b)),
+                lcx::Input::Up(b) => write!(f, "ctrl up {}", on(b)),
+                lcx::Input::Down(b) => write!(f, "ctrl down {}", on(b)),
+                lcx::Input::Left(b) => write!(f, "ctrl left {}", on(b)),
+                lcx::Input::Right(b) => write!(f, "ctrl right {}", on(b)),
+                lcx::Input::Select(i, b) => write!(f, "ctrl select {i} {}", on(b)),
+            },
+            Input::Tempo(bpm) => write!(f, "tempo {bpm}"),
+            Input::Meter(beats) => write!(f, "meter {beats}"),
+            Input::Devices(Devices { pad, ctrl }) => write!(f, "devices {} {}", on(pad), on(ctrl)),
+        }
+    }
+}
+
+/// Parses a script of timestamped inputs, one per line:
+///
+/// ```text
+/// # seconds  device  input...
+/// 0.0   pad   0 0 1.0
+/// 0.5   pad   0 0 0.0
+/// 0.25  ctrl  knob 0 1 -0.5
+/// 0.25  ctrl  slider 7 0.8
+/// 1.0   ctrl  button 2 0 on
+/// 1.0   ctrl  up on
+/// 1.0   ctrl  select 3 off
+/// 2.0   tempo 140
+/// 2.0   meter 3
+/// 3.0   devices off on
+/// ```
+///
+/// Events are returned sorted by time.
+pub fn parse_script(src: &str) -> Result<Vec<Event>> {
+    let mut events = vec![];
+
+    for (i, line) in src.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let event = parse_event(line).with_context(|| format!("line {}: '{line}'", i + 1))?;
+        events.push(event);
+    }
+
+    events.sort_by(|a, b| a.t.total_cmp(&b.t));
+    Ok(events)
+}
+
+fn parse_event(line: &str) -> Result<Event> {
+    let (t, input) = line.split_once(char::is_whitespace).ok_or_else(|| anyhow!("missing field"))?;
+    Ok(Event { t: t.parse()?, input: parse_input(input)? })
+}
+
+/// Parses a single input in the script format, without the timestamp.
+pub fn parse_input(s: &str) -> Result<Input> {
+    let mut words = s.split_whitespace();
+    let mut next = || words.next().ok_or_else(|| anyhow!("missing field"));
+
+    fn int(s: &str) -> Result<i8> { Ok(s.parse()?) }
+    fn float(s: &str) -> Result<f64> { Ok(s.parse()?) }
+    fn bool(s: &str) -> Result<bool> {
+        Ok(match s {
+            "on" | "1" | "true" => true,
+            "off" | "0" | "false" => false,
+            _ => bail!("expected on/off: {s}"),
+        })
+    }
+
+    Ok(match next()? {
+        "pad" => Input::Pad(lpx::Input { x: int(next()?)?, y: int(next()?)?, v: float(next()?)? }),
+        "ctrl" => Input::Ctrl(match next()? {
+            "knob" => lcx::Input::Knob(int(next()?)?, int(next()?)?, float(next()?)?),
+            "slider" => lcx::Input::Slider(int(next()?)?, float(next()?)?),
+            "button" => lcx::Input::Button(int(next()?)?, int(next()?)?, bool(next()?)?),
+            "up" => lcx::Input::Up(bool(next()?)?),
+            "down" => lcx::Input::Down(bool(next()?)?),
+            "left" => lcx::Input::Left(bool(next()?)?),
+            "right" => lcx::Input::Right(bool(next()?)?),
+            "select" => lcx::Input::Select(int(next()?)?, bool(next()?)?),
+            other => bail!("unknown ctrl input: {other}"),
+        }),
+        "tempo" => Input::Tempo(float(next()?)?),
+        "meter" => Input::Meter(next()?.parse()?),
+        "devices" => Input::Devices(Devices { pad: bool(next()?)?, ctrl: bool(next()?)? }),
+        other => bail!("unknown device: {other}"),
+    })
+}
+
+pub struct Options {
+    pub rate: u32,
+    pub duration: f64,
+    pub format: wav::Format,
+}
+
+/// Renders `p` offline into a stereo WAV file at `out`, feeding `events` to the program at their timestamps.
+pub fn render(mut p: Program, out: impl AsRef<Path>, events: &[Event], opts: Options) -> Result<()> {
+
+    let mut wav = wav::Writer::create(out, opts.rate, opts.format)?;
+    let frames = (opts.duration * opts.rate as f64).round() as u64;
+
+    const BLOCK: usize = 256;
+    let mut left = [0.0; BLOCK];
+    let mut right = [0.0; BLOCK];
+
+    // events apply from the first frame at or after their timestamp
+    let frame_of = |e: &Event| (e.t * opts.rate as f64).ceil() as u64;
+    let mut events = events.iter().peekable();
+    while p.transport.frame < frames {
+        let frame = p.transport.frame;
+        while let Some(event) = events.next_if(|e| frame_of(e) <= frame) {
+            event.input.apply(&mut p);
+        }
+
+        // split the block at the next event
+        let mut n = BLOCK.min((frames - frame) as usize);
+        if let Some(next) = events.peek() {
+            n = n.min((frame_of(next) - frame) as usize);
+        }
+
+        p.process(&mut left[..n], &mut right[..n]);
+        for i in 0..n {
+            wav.write(left[i], right[i])?;
+        }
+    }
+
+    wav.finish()?;
+    if let Some(failure) = p.take_failure() {
+        println!("{failure}, the rest of the render is silent");
+    }
+    p.unload();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_parses_back() {
+        let inputs = [
+            Input::Pad(lpx::Input { x: 3, y: 7, v: 0.125 }),
+            Input::Ctrl(lcx::Input::Knob(1, 2, -0.5)),
+            Input::Ctrl(lcx::Input::Slider(7, 0.8)),
+            Input::Ctrl(lcx::Input::Button(2, 0, true)),
+            Input::Ctrl(lcx::Input::Up(true)),
+            Input::Ctrl(lcx::Input::Down(false)),
+            Input::Ctrl(lcx::Input::Left(true)),
+            Input::Ctrl(lcx::Input::Right(false)),
+            Input::Ctrl(lcx::Input::Select(3, false)),
+            Input::Tempo(133.5),
+            Input::Meter(7),
+            Input::Devices(Devices { pad: false, ctrl: true }),
+        ];
+        let script = inputs.iter()
+            .enumerate()
+            .map(|(i, &input)| Event { t: i as f64 * 0.25, input }.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let events = parse_script(&script).unwrap();
+        assert_eq!(events.iter().map(|e| e.input).collect::<Vec<_>>(), inputs);
+        for (i, event) in events.iter().enumerate() {
+            assert_eq!(event.t, i as f64 * 0.25);
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use std::fs::File;
+use std::io::{LineWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+
+use crate::engine::Input;
+use crate::render::{self, Event};
+use crate::smf;
+
+/// Whether `path` is a Standard MIDI File rather than a script.
+fn is_midi(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e == "mid" || e == "midi")
+}
+
+/// Reads a render script, or a MIDI file played on the piano at `octave`.
+pub fn load_events(path: impl AsRef<Path>, octave: i8) -> Result<Vec<Event>> {
+    let path = path.as_ref();
+    let events = match is_midi(path) {
+        true => smf::read(path, octave),
+        false => std::fs::read_to_string(path).map_err(Into::into).and_then(|src| render::parse_script(&src)),
+    };
+    events.with_context(|| format!("failed to read {}", path.display()))
+}
+
+/// Logs every input sent to the program in the render script format, timed
+/// from the start of the session, so it can be replayed live with `--replay`
+/// or rendered offline with `render --script`.
+///
+/// Logging to a `.mid` file records only the piano and tempo, as a Standard MIDI File.
+pub struct Log {
+    out: Out,
+    start: Instant,
+}
+
+enum Out {
+    Script(LineWriter<File>),
+    Midi(smf::Recorder, PathBuf),
+}
+
+impl Log {
+    pub fn create(path: impl AsRef<Path>, start: Instant, octave: i8) -> Result<Self> {
+        let path = path.as_ref();
+        let out = match is_midi(path) {
+            true => Out::Midi(smf::Recorder::new(octave), path.to_owned()),
+            false => {
+                let out = File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+                Out::Script(LineWriter::new(out))
+            },
+        };
+        Ok(Self { out, start })
+    }
+
+    pub fn write(&mut self, at: Instant, input: Input) {
+        let event = Event { t: at.saturating_duration_since(self.start).as_secs_f64(), input };
+        match &mut self.out {
+            Out::Script(out) => {
+                if let Err(e) = writeln!(out, "{event}") {
+                    println!("failed to log input: {e}");
+                }
+            },
+            Out::Midi(recorder, _) => recorder.record(event.t, event.input),
+        }
+    }
+
+    /// Writes out the log if it's kept in memory until the end.
+    pub fn finish(self) -> Result<()> {
+        if let Out::Midi(recorder, path) = self.out {
+            recorder.save(&path).with_context(|| format!("failed to write {}", path.display()))?;
+            println!("saved {}", path.display());
+        }
+        Ok(())
+    }
+}
+
+/// Plays back a logged session in real time.
+///
+/// Logged `Input::Devices` are skipped: which controllers are plugged in is
+/// up to the live session, they only matter to an offline `render --script`.
+pub struct Replay {
+    events: std::iter::Peekable<std::vec::IntoIter<Event>>,
+    start: Instant,
+}
+
+impl Replay {
+    pub fn load(path: impl AsRef<Path>, start: Instant, octave: i8) -> Result<Self> {
+        let path = path.as_ref();
+        let mut events = load_events(path, octave)?;
+        events.retain(|e| !matches!(e.input, Input::Devices(_)));
+        println!("replaying {} inputs from {}", events.len(), path.display());
+        Ok(Self { events: events.into_iter().peekable(), start })
+    }
+
+    fn at(start: Instant, event: &Event) -> Instant {
+        start + Duration::from_secs_f64(event.t.max(0.0))
+    }
+
+    /// Inputs that are due by `now`, with when they were originally played.
+    pub fn due(&mut self, now: Instant) -> Vec<(Instant, Input)> {
+        let mut due = vec![];
+        let start = self.start;
+        while let Some(event) = self.events.next_if(|e| Self::at(start, e) <= now) {
+            due.push((Self::at(start, &event), event.input));
+        }
+        due
+    }
+
+    /// When the next input is due, to wake up for.
+    pub fn deadline(&mut self) -> Option<Instant> {
+        self.events.peek().map(|e| Self::at(self.start, e))
+    }
+}
@@ -0,0 +1,304 @@
+use std::path::Path;
+
+use anyhow::{Result, anyhow, bail};
+use types::launchpad_x as lpx;
+
+use crate::engine::Input;
+use crate::render::Event;
+
+/// Pads of the program's `Piano` layout, from its lowest note up a semitone at a time.
+/// Has to be kept in sync with `logic::pad::Piano`.
+const PIANO: [(i8, i8); 13] = [
+    (0, 0), (1, 1), (1, 0), (2, 1), (2, 0), (3, 0), (4, 1),
+    (4, 0), (5, 1), (5, 0), (6, 1), (6, 0), (7, 0),
+];
+
+/// Ticks per quarter note in files we write.
+const DIVISION: u16 = 480;
+
+/// MIDI note of the lowest pad when the piano is at `octave`.
+fn base(octave: i8) -> i32 {
+    (octave as i32 + 1) * 12
+}
+
+/// Records pad performances and tempo changes as a format 0 Standard MIDI File.
+///
+/// The pad reports the initial velocity and later pressure the same way, so the
+/// first non-zero value of a pad is a note on, later ones are polyphonic
+/// aftertouch, and zero is the note off.
+pub struct Recorder {
+    octave: i8,
+    held: [bool; 13],
+    /// Seconds and message, in order.
+    events: Vec<(f64, Vec<u8>)>,
+    tempos: Vec<(f64, f64)>,
+}
+
+impl Recorder {
+    pub fn new(octave: i8) -> Self {
+        Self { octave, held: [false; 13], events: vec![], tempos: vec![] }
+    }
+
+    pub fn record(&mut self, t: f64, input: Input) {
+        match input {
+            Input::Pad(lpx::Input { x, y, v }) => {
+                let Some(i) = PIANO.iter().position(|&p| p == (x, y)) else { return };
+                let note = (base(self.octave) + i as i32).clamp(0, 127) as u8;
+                let value = (v * 127.0).round().clamp(1.0, 127.0) as u8;
+
+                let msg = match (v > 0.0, self.held[i]) {
+                    (true, false) => vec![0x90, note, value],
+                    (true, true) => vec![0xA0, note, value],
+                    (false, true) => vec![0x80, note, 0],
+                    (false, false) => return,
+                };
+                self.held[i] = v > 0.0;
+                self.events.push((t, msg));
+            },
+            Input::Tempo(bpm) => self.tempos.push((t, bpm)),
+            _ => {},
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        std::fs::write(path, self.encode())?;
+        Ok(())
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut tempos = self.tempos.clone();
+        if tempos.first().is_none_or(|&(t, _)| t > 0.0) {
+            tempos.insert(0, (0.0, 120.0));
+        }
+
+        // merge tempo changes in as meta events, so ticks are counted at the tempo in effect
+        let mut events = self.events.iter().map(|(t, msg)| (*t, msg.clone(), None)).collect::<Vec<_>>();
+        for &(t, bpm) in &tempos {
+            let us = (60_000_000.0 / bpm) as u32;
+            events.push((t, vec![0xFF, 0x51, 0x03, (us >> 16) as u8, (us >> 8) as u8, us as u8], Some(bpm)));
+        }
+        events.sort_by(|a, b| a.0.total_cmp(&b.0).then(b.2.is_some().cmp(&a.2.is_some())));
+
+        let mut track = vec![];
+        let (mut t0, mut ticks0, mut bpm, mut last) = (0.0, 0.0, 120.0, 0u64);
+        for (t, msg, tempo) in events {
+            let ticks = ticks0 + (t - t0) * bpm / 60.0 * DIVISION as f64;
+            let tick = ticks.round() as u64;
+            write_vlq(&mut track, tick - last);
+            track.extend_from_slice(&msg);
+            last = tick;
+
+            if let Some(tempo) = tempo {
+                (t0, ticks0, bpm) = (t, ticks, tempo);
+            }
+        }
+        write_vlq(&mut track, 0);
+        track.extend_from_slice(&[0xFF, 0x2F, 0x00]);
+
+        let mut out = vec![];
+        out.extend_from_slice(b"MThd");
+        out.extend_from_slice(&6u32.to_be_bytes());
+        out.extend_from_slice(&0u16.to_be_bytes());
+        out.extend_from_slice(&1u16.to_be_bytes());
+        out.extend_from_slice(&DIVISION.to_be_bytes());
+        out.extend_from_slice(b"MTrk");
+        out.extend_from_slice(&(track.len() as u32).to_be_bytes());
+        out.extend_from_slice(&track);
+        out
+    }
+}
+
+fn write_vlq(out: &mut Vec<u8>, mut v: u64) {
+    let mut bytes = vec![(v & 0x7F) as u8];
+    v >>= 7;
+    while v > 0 {
+        bytes.push((v & 0x7F) as u8 | 0x80);
+        v >>= 7;
+    }
+    out.extend(bytes.iter().rev());
+}
+
+/// Reads a format 0 or 1 Standard MIDI File as pad input and tempo changes, as if
+/// played on the piano at `octave`. Notes outside its range are moved by octaves
+/// into it, the MIDI channel is ignored.
+pub fn read(path: impl AsRef<Path>, octave: i8) -> Result<Vec<Event>> {
+    decode(&std::fs::read(path)?, octave)
+}
+
+fn decode(data: &[u8], octave: i8) -> Result<Vec<Event>> {
+    let mut r = Reader { data, pos: 0 };
+
+    if r.bytes(4)? != b"MThd" {
+        bail!("not a Standard MIDI File");
+    }
+    let len = r.u32()? as usize;
+    if len < 6 {
+        bail!("header chunk too short");
+    }
+    let (format, tracks, division) = (r.u16()?, r.u16()?, r.u16()?);
+    r.pos += len - 6;
+    if format > 1 {
+        bail!("unsupported SMF format {format}");
+    }
+    if division & 0x8000 != 0 {
+        bail!("SMPTE time division isn't supported");
+    }
+
+    // (tick, order, message), with meta events as [0xFF, type, data...]
+    let mut messages = vec![];
+    for _ in 0..tracks {
+        if r.bytes(4)? != b"MTrk" {
+            bail!("expected a track chunk");
+        }
+        let end = r.u32()? as usize + r.pos;
+        let (mut tick, mut status) = (0u64, 0u8);
+        while r.pos < end {
+            tick += r.vlq()?;
+            let mut b = r.u8()?;
+            if b < 0x80 {
+                if status == 0 {
+                    bail!("data byte without a status");
+                }
+                // running status
+                r.pos -= 1;
+                b = status;
+            }
+
+            match b {
+                0xFF => {
+                    let kind = r.u8()?;
+                    let len = r.vlq()? as usize;
+                    let mut msg = vec![0xFF, kind];
+                    msg.extend_from_slice(r.bytes(len)?);
+                    messages.push((tick, messages.len(), msg));
+                },
+                0xF0 | 0xF7 => {
+                    let len = r.vlq()? as usize;
+                    r.bytes(len)?;
+                },
+                0x80..=0xEF => {
+                    status = b;
+                    let len = if matches!(b & 0xF0, 0xC0 | 0xD0) { 1 } else { 2 };
+                    let mut msg = vec![b];
+                    msg.extend_from_slice(r.bytes(len)?);
+                    messages.push((tick, messages.len(), msg));
+                },
+                _ => bail!("invalid status byte {b:#04x}"),
+            }
+        }
+        r.pos = end;
+    }
+    messages.sort_by_key(|&(tick, order, _)| (tick, order));
+
+    let (lo, hi) = (base(octave), base(octave) + 12);
+    let mut folded = 0;
+    let (mut t0, mut tick0, mut us_per_beat) = (0.0, 0u64, 500_000.0);
+    let mut events = vec![];
+    for (tick, _, msg) in messages {
+        let t = t0 + (tick - tick0) as f64 * us_per_beat / 1e6 / division as f64;
+
+        let (note, v) = match (msg[0] & 0xF0, msg.as_slice()) {
+            (0xF0, &[0xFF, 0x51, a, b, c]) => {
+                (t0, tick0) = (t, tick);
+                us_per_beat = u32::from_be_bytes([0, a, b, c]) as f64;
+                events.push(Event { t, input: Input::Tempo(60e6 / us_per_beat) });
+                continue;
+            },
+            (0x90, &[_, note, vel]) => (note, vel),
+            (0x80, &[_, note, _]) => (note, 0),
+            (0xA0, &[_, note, pressure]) => (note, pressure.max(1)),
+            _ => continue,
+        };
+
+        let mut note = note as i32;
+        if note < lo || note > hi {
+            folded += 1;
+            note = lo + (note - lo).rem_euclid(12);
+        }
+        let (x, y) = PIANO[(note - lo) as usize];
+        events.push(Event { t, input: Input::Pad(lpx::Input { x, y, v: v as f64 / 127.0 }) });
+    }
+
+    if folded > 0 {
+        println!("moved {folded} notes outside the piano's range by octaves");
+    }
+    Ok(events)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8]> {
+        let bytes = self.data.get(self.pos..self.pos + n).ok_or_else(|| anyhow!("unexpected end of file"))?;
+        self.pos += n;
+        Ok(bytes)
+    }
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.bytes(2)?.try_into()?))
+    }
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.bytes(4)?.try_into()?))
+    }
+    fn vlq(&mut self) -> Result<u64> {
+        let mut v = 0;
+        for _ in 0..4 {
+            let b = self.u8()?;
+            v = (v << 7) | (b & 0x7F) as u64;
+            if b & 0x80 == 0 {
+                return Ok(v);
+            }
+        }
+        bail!("variable length quantity too long")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pad(i: usize, v: f64) -> Input {
+        let (x, y) = PIANO[i];
+        Input::Pad(lpx::Input { x, y, v })
+    }
+
+    #[test]
+    fn roundtrip() {
+        // on tick boundaries at either tempo, so nothing is lost to rounding
+        let played = [
+            (0.5, pad(0, 1.0)),
+            (0.5, pad(12, 64.0 / 127.0)),
+            (1.0, Input::Tempo(100.0)),
+            (1.6, pad(12, 100.0 / 127.0)),
+            (2.2, pad(0, 0.0)),
+            (2.2, pad(12, 0.0)),
+        ];
+        let mut recorder = Recorder::new(3);
+        for &(t, input) in &played {
+            recorder.record(t, input);
+        }
+
+        let events = decode(&recorder.encode(), 3).unwrap();
+        // the file starts with the default tempo
+        let expected = [(0.0, Input::Tempo(120.0))].into_iter().chain(played).collect::<Vec<_>>();
+        assert_eq!(events.len(), expected.len(), "{events:?}");
+        for (event, (t, input)) in events.iter().zip(expected) {
+            assert!((event.t - t).abs() < 1e-9, "{event:?} should be at {t}");
+            assert_eq!(event.input, input, "at {t}");
+        }
+    }
+
+    #[test]
+    fn notes_outside_the_piano_are_folded_in() {
+        let mut recorder = Recorder::new(5);
+        recorder.record(0.0, pad(4, 1.0));
+        // same pad, two octaves up
+        let events = decode(&recorder.encode(), 3).unwrap();
+        assert_eq!(events[1].input, pad(4, 1.0));
+    }
+}
@@ -0,0 +1,66 @@
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use types::ffi::Encoding;
+
+/// Rotating set of serialized program states in a directory, named by the
+/// time they were taken so they sort oldest first.
+pub struct Snapshots {
+    dir: PathBuf,
+    keep: usize,
+    last: Option<Vec<u8>>,
+}
+
+impl Snapshots {
+    pub fn new(dir: impl Into<PathBuf>, keep: usize) -> Self {
+        Self { dir: dir.into(), keep, last: None }
+    }
+
+    pub fn list(&self) -> Result<Vec<PathBuf>> {
+        if !self.dir.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut paths = vec![];
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|e| e == "json" || e == "bin") {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        Ok(paths)
+    }
+
+    pub fn latest(&self) -> Result<Option<PathBuf>> {
+        Ok(self.list()?.pop())
+    }
+
+    /// Writes `state` as a new snapshot unless it's identical to the last one,
+    /// then deletes the oldest snapshots beyond `keep`.
+    pub fn save(&mut self, state: &[u8]) -> Result<Option<PathBuf>> {
+        if self.last.as_deref() == Some(state) {
+            return Ok(None);
+        }
+
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("failed to create {}", self.dir.display()))?;
+
+        let millis = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+        let path = self.dir.join(format!("{millis:013}.{}", Encoding::detect(state).extension()));
+
+        // write then rename, so a crash never leaves a truncated snapshot behind
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, state)?;
+        std::fs::rename(&tmp, &path)?;
+        self.last = Some(state.to_vec());
+
+        let paths = self.list()?;
+        for old in &paths[..paths.len().saturating_sub(self.keep)] {
+            std::fs::remove_file(old)?;
+        }
+
+        Ok(Some(path))
+    }
+}
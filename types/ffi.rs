@@ -0,0 +1,261 @@
+use std::mem::{ManuallyDrop, align_of, offset_of, size_of};
+
+use crate::launch_control_xl as lcx;
+use crate::launchpad_x as lpx;
+
+/// Bump whenever an export's signature or the meaning of a type changes.
+pub const ABI_VERSION: u32 = 9;
+
+/// Exported by the library through `abi`, and checked by the host before
+/// it resolves anything else.
+#[repr(C)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Abi {
+    pub version: u32,
+    /// Hash of the size, alignment and field offsets of every type crossing the boundary.
+    pub layout: u64,
+}
+
+impl Abi {
+    pub const fn current() -> Self {
+        Self { version: ABI_VERSION, layout: layout_hash() }
+    }
+}
+
+const fn layout_hash() -> u64 {
+    let layout = [
+        size_of::<Status>(), align_of::<Status>(),
+        size_of::<Bytes>(), align_of::<Bytes>(),
+        size_of::<Abi>(), align_of::<Abi>(),
+        size_of::<Encoding>(),
+        size_of::<Transport>(), align_of::<Transport>(),
+        offset_of!(Transport, frame), offset_of!(Transport, rate), offset_of!(Transport, seconds),
+        offset_of!(Transport, tempo), offset_of!(Transport, beat),
+        offset_of!(Transport, beats_per_bar), offset_of!(Transport, bar),
+        offset_of!(Transport, bar_origin), offset_of!(Transport, bars_before),
+        size_of::<Devices>(), offset_of!(Devices, pad), offset_of!(Devices, ctrl),
+
+        size_of::<lpx::Input>(), align_of::<lpx::Input>(),
+        offset_of!(lpx::Input, x), offset_of!(lpx::Input, y), offset_of!(lpx::Input, v),
+        size_of::<lpx::Output>(), align_of::<lpx::Output>(),
+
+        size_of::<lcx::Input>(), align_of::<lcx::Input>(),
+        size_of::<lcx::Output>(), align_of::<lcx::Output>(),
+        offset_of!(lcx::Output, knobs), offset_of!(lcx::Output, buttons),
+        offset_of!(lcx::Output, up), offset_of!(lcx::Output, down),
+        offset_of!(lcx::Output, left), offset_of!(lcx::Output, right),
+        offset_of!(lcx::Output, select),
+        size_of::<lcx::Color>(),
+    ];
+
+    // FNV-1a
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let mut i = 0;
+    while i < layout.len() {
+        hash ^= layout[i] as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+        i += 1;
+    }
+    hash
+}
+
+/// Result of a call into the program library.
+#[repr(i32)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Status {
+    Ok = 0,
+    /// The call failed, e.g. a state that couldn't be deserialized.
+    Error = 1,
+    /// The call panicked. The instance should be considered poisoned.
+    Panic = 2,
+}
+
+/// How `serialize` encodes the state. JSON can be read and edited by hand;
+/// binary (MessagePack) is much faster for large states. Both keep field names,
+/// so either can be migrated from older versions.
+#[repr(u8)]
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub enum Encoding {
+    #[default]
+    Json = 0,
+    Binary = 1,
+}
+
+/// Prefix of binary states, so `deserialize` can tell the two apart.
+pub const BINARY_MAGIC: &[u8; 8] = b"SYNTHBIN";
+
+impl Encoding {
+    pub fn detect(state: &[u8]) -> Self {
+        if state.starts_with(BINARY_MAGIC) { Encoding::Binary } else { Encoding::Json }
+    }
+
+    /// File extension for snapshots in this encoding.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Encoding::Json => "json",
+            Encoding::Binary => "bin",
+        }
+    }
+}
+
+impl std::str::FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "json" => Ok(Encoding::Json),
+            "bin" | "binary" => Ok(Encoding::Binary),
+            _ => Err(format!("unknown state format: {s} (expected json or binary)")),
+        }
+    }
+}
+
+/// Position of the host's audio clock, counted in whole frames so it never drifts.
+#[repr(C)]
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Transport {
+    /// Frames rendered since the host started, carried across reloads.
+    pub frame: u64,
+    pub rate: u32,
+    /// `frame / rate`.
+    pub seconds: f64,
+    /// Beats per minute.
+    pub tempo: f64,
+    /// Beats since the start, with the phase within the current beat as the fraction.
+    pub beat: f64,
+    pub beats_per_bar: u32,
+    /// Whole bars since the start.
+    pub bar: u64,
+    /// Beat the current meter started counting bars from, on a bar line.
+    pub bar_origin: f64,
+    /// Bars played before `bar_origin`, in earlier meters.
+    pub bars_before: u64,
+}
+
+impl Transport {
+    pub fn new(rate: u32) -> Self {
+        Self { frame: 0, rate, seconds: 0.0, tempo: 120.0, beat: 0.0, beats_per_bar: 4, bar: 0, bar_origin: 0.0, bars_before: 0 }
+    }
+
+    /// Changes the beats per bar from the start of the current bar on, so the
+    /// bars already played keep their numbers.
+    pub fn set_meter(&mut self, beats_per_bar: u32) {
+        self.bar_origin += (self.bar - self.bars_before) as f64 * self.beats_per_bar.max(1) as f64;
+        self.bars_before = self.bar;
+        self.beats_per_bar = beats_per_bar.max(1);
+        self.bar = self.bar_at(self.beat);
+    }
+
+    /// Beats since the current meter started.
+    fn since_origin(&self) -> f64 {
+        (self.beat - self.bar_origin).max(0.0)
+    }
+
+    fn bar_at(&self, beat: f64) -> u64 {
+        self.bars_before + ((beat - self.bar_origin).max(0.0) / self.beats_per_bar.max(1) as f64) as u64
+    }
+
+    /// Seconds per frame.
+    pub fn dt(&self) -> f64 {
+        1.0 / self.rate as f64
+    }
+
+    /// Position within the current beat, from 0 to 1.
+    pub fn beat_phase(&self) -> f64 {
+        self.beat.fract()
+    }
+
+    /// Position within the current bar, from 0 to 1.
+    pub fn bar_phase(&self) -> f64 {
+        let n = self.beats_per_bar.max(1) as f64;
+        (self.since_origin() % n) / n
+    }
+
+    /// Index of the current beat within its bar.
+    pub fn beat_in_bar(&self) -> u32 {
+        (self.since_origin() as u64 % self.beats_per_bar.max(1) as u64) as u32
+    }
+
+    /// Phase from 0 to 1 of something repeating every `beats` beats, e.g. 0.25 for 16ths.
+    pub fn sync(&self, beats: f64) -> f64 {
+        (self.beat / beats).fract()
+    }
+
+    /// The position `frames` later at the current tempo.
+    pub fn offset(&self, frames: u64) -> Self {
+        let frame = self.frame + frames;
+        let beat = self.beat + frames as f64 * self.tempo / (60.0 * self.rate as f64);
+        Self {
+            frame,
+            seconds: frame as f64 / self.rate as f64,
+            beat,
+            bar: self.bar_at(beat),
+            ..*self
+        }
+    }
+}
+
+/// Which controllers are currently plugged in.
+#[repr(C)]
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub struct Devices {
+    pub pad: bool,
+    pub ctrl: bool,
+}
+
+/// Byte buffer allocated by the library, which has to be given back to
+/// the library's `free_bytes` since the two sides may not share an allocator.
+#[repr(C)]
+pub struct Bytes {
+    pub ptr: *mut u8,
+    pub len: usize,
+    pub cap: usize,
+}
+
+impl Bytes {
+    pub const fn empty() -> Self {
+        Self { ptr: std::ptr::null_mut(), len: 0, cap: 0 }
+    }
+
+    pub fn from_vec(v: Vec<u8>) -> Self {
+        let mut v = ManuallyDrop::new(v);
+        Self { ptr: v.as_mut_ptr(), len: v.len(), cap: v.capacity() }
+    }
+
+    /// # Safety
+    /// Must only be called on the side that allocated it, with a buffer from `from_vec`.
+    pub unsafe fn into_vec(self) -> Vec<u8> {
+        Vec::from_raw_parts(self.ptr, self.len, self.cap)
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        if self.ptr.is_null() {
+            return &[];
+        }
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Advances `t` by `beats` at 120 bpm and 48k.
+    fn play(t: Transport, beats: u64) -> Transport {
+        t.offset(beats * 24_000)
+    }
+
+    #[test]
+    fn meter_change_keeps_past_bars() {
+        // two bars of 4, then switching to 3 halfway through the third bar,
+        // which becomes a bar of 3
+        let mut t = play(Transport::new(48_000), 10);
+        assert_eq!((t.bar, t.beat_in_bar()), (2, 2));
+        t.set_meter(3);
+        assert_eq!((t.bar, t.beat_in_bar()), (2, 2));
+
+        let t = play(t, 5);
+        assert_eq!((t.bar, t.beat_in_bar()), (4, 1));
+        assert_eq!(t.bar_phase(), 1.0 / 3.0);
+    }
+}
@@ -1,4 +1,5 @@
-#[derive(Clone, Copy, Debug)]
+#[repr(C, u8)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum Input {
     Knob(i8, i8, f64),
     Slider(i8, f64),
@@ -12,6 +13,8 @@ pub enum Input {
     Select(i8, bool),
 }
 
+#[repr(C)]
+#[derive(Clone, Copy)]
 pub struct Output {
     pub knobs: [Color; 24],
     pub buttons: [Color; 16],
@@ -40,6 +43,7 @@ pub enum Mode {
     Factory
 }
 
+#[repr(u8)]
 #[derive(Copy, Clone, Debug)]
 pub enum Color {
     Off,
@@ -1,6 +1,18 @@
-pub type Input = ((i8, i8), f64);
-pub type Output = [(f64, f64, f64); 81];
+/// Pad at (`x`, `y`) pressed with velocity or pressure `v`, 0 on release.
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Input {
+    pub x: i8,
+    pub y: i8,
+    pub v: f64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Rgb(pub f64, pub f64, pub f64);
+
+pub type Output = [Rgb; 81];
 
 pub fn clear() -> Output {
-    [(0.0, 0.0, 0.0); 81]
+    [Rgb(0.0, 0.0, 0.0); 81]
 }
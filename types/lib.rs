@@ -1,2 +1,3 @@
+pub mod ffi;
 pub mod launch_control_xl;
 pub mod launchpad_x;
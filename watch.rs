@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, anyhow};
+use inotify::{Event, EventMask, Inotify, WatchDescriptor, WatchMask, Watches};
+
+use crate::reactor::Waker;
+
+/// Watches a directory for a rebuilt library.
+///
+/// Cargo hard links the finished artifact into place, other tools write it in
+/// place or rename a temp file over it, so any of those count as a change. A
+/// change is only reported once no further events arrived for `debounce`.
+pub struct Watcher {
+    changes: Receiver<()>,
+    debounce: Duration,
+    pending: Option<Instant>,
+}
+
+impl Watcher {
+    pub fn new(dir: impl AsRef<Path>, library: impl AsRef<Path>, debounce: Duration, waker: Waker) -> Result<Self> {
+        let name = library.as_ref().file_name()
+            .ok_or_else(|| anyhow!("invalid library path: {}", library.as_ref().display()))?
+            .to_owned();
+
+        let inotify = Inotify::init()?;
+        inotify.watches().add(dir, WatchMask::CREATE | WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO)?;
+
+        let changes = spawn(inotify, waker, move |event| event.name == Some(name.as_os_str()));
+        Ok(Self { changes, debounce, pending: None })
+    }
+
+    /// Whether the library changed and has settled since the last call.
+    pub fn poll(&mut self) -> Result<bool> {
+        drain(&self.changes, &mut self.pending)?;
+        Ok(settled(&mut self.pending, self.debounce))
+    }
+
+    /// When a pending change will have settled, to wake up for.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.pending.map(|at| at + self.debounce)
+    }
+}
+
+/// Recursively watches a source tree for changes to `.rs` files and `Cargo.toml`s.
+pub struct SourceWatcher {
+    changes: Receiver<()>,
+    debounce: Duration,
+    pending: Option<Instant>,
+}
+
+impl SourceWatcher {
+    const MASK: WatchMask = WatchMask::CREATE
+        .union(WatchMask::CLOSE_WRITE)
+        .union(WatchMask::MOVED_TO)
+        .union(WatchMask::MOVED_FROM)
+        .union(WatchMask::DELETE);
+
+    pub fn new(root: impl AsRef<Path>, debounce: Duration, waker: Waker) -> Result<Self> {
+        let inotify = Inotify::init()?;
+        let mut watches = inotify.watches();
+        let mut dirs = HashMap::new();
+        Self::add(&mut watches, &mut dirs, root.as_ref())?;
+
+        let changes = spawn(inotify, waker, move |event| {
+            let Some(name) = event.name else { return false };
+
+            if event.mask.contains(EventMask::ISDIR) {
+                if !event.mask.intersects(EventMask::CREATE | EventMask::MOVED_TO) {
+                    return false;
+                }
+                // a new directory may already have sources in it
+                let Some(dir) = dirs.get(&event.wd).map(|d| d.join(name)) else { return false };
+                if let Err(e) = Self::add(&mut watches, &mut dirs, &dir) {
+                    println!("failed to watch {}: {e}", dir.display());
+                }
+                return true;
+            }
+
+            let name = Path::new(name);
+            name.extension().is_some_and(|e| e == "rs") || name == Path::new("Cargo.toml")
+        });
+
+        Ok(Self { changes, debounce, pending: None })
+    }
+
+    /// Watches `dir` and every directory below it, except build output.
+    fn add(watches: &mut Watches, dirs: &mut HashMap<WatchDescriptor, PathBuf>, dir: &Path) -> Result<()> {
+        let wd = watches.add(dir, Self::MASK)?;
+        dirs.insert(wd, dir.to_owned());
+
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() && entry.file_name() != "target" {
+                Self::add(watches, dirs, &entry.path())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether any sources changed and have settled since the last call.
+    pub fn poll(&mut self) -> Result<bool> {
+        drain(&self.changes, &mut self.pending)?;
+        Ok(settled(&mut self.pending, self.debounce))
+    }
+
+    /// When a pending change will have settled, to wake up for.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.pending.map(|at| at + self.debounce)
+    }
+}
+
+/// Reads events on their own thread, since a blocking read is the only way to
+/// hear about them without polling. Sends a message and wakes the control loop
+/// for every event `relevant` accepts.
+fn spawn(
+    mut inotify: Inotify,
+    waker: Waker,
+    mut relevant: impl FnMut(&Event<&OsStr>) -> bool + Send + 'static,
+) -> Receiver<()> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut buf = [0; 4096];
+        loop {
+            let events = match inotify.read_events_blocking(&mut buf) {
+                Ok(events) => events,
+                Err(e) => {
+                    println!("file watcher stopped: {e}");
+                    break;
+                },
+            };
+
+            let mut changed = false;
+            for event in events {
+                changed |= relevant(&event);
+            }
+            if changed {
+                if tx.send(()).is_err() {
+                    break;
+                }
+                waker.wake();
+            }
+        }
+    });
+    rx
+}
+
+/// Marks a change as pending if any arrived since the last call.
+fn drain(changes: &Receiver<()>, pending: &mut Option<Instant>) -> Result<()> {
+    loop {
+        match changes.try_recv() {
+            Ok(()) => *pending = Some(Instant::now()),
+            Err(TryRecvError::Empty) => return Ok(()),
+            Err(TryRecvError::Disconnected) => return Err(anyhow!("file watcher stopped")),
+        }
+    }
+}
+
+/// Whether a pending change has been quiet for at least `debounce`, clearing it if so.
+fn settled(pending: &mut Option<Instant>, debounce: Duration) -> bool {
+    match *pending {
+        Some(at) if at.elapsed() >= debounce => {
+            *pending = None;
+            true
+        },
+        _ => false,
+    }
+}
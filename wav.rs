@@ -0,0 +1,207 @@
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use anyhow::{Result, bail};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Format {
+    Int16,
+    Int24,
+    Float32,
+}
+
+impl Format {
+    fn bytes(self) -> u16 {
+        match self {
+            Format::Int16 => 2,
+            Format::Int24 => 3,
+            Format::Float32 => 4,
+        }
+    }
+}
+
+impl std::str::FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "16" | "i16" => Format::Int16,
+            "24" | "i24" => Format::Int24,
+            "32f" | "f32" => Format::Float32,
+            _ => bail!("unknown sample format: {s} (expected i16, i24 or f32)"),
+        })
+    }
+}
+
+/// Stereo WAV writer. The header is written up front with empty sizes,
+/// which are patched in by `finish`.
+pub struct Writer<W: Write + Seek> {
+    out: W,
+    format: Format,
+    frames: u32,
+}
+
+impl Writer<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>, rate: u32, format: Format) -> Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?), rate, format)
+    }
+}
+
+impl<W: Write + Seek> Writer<W> {
+    const CHANNELS: u16 = 2;
+
+    pub fn new(mut out: W, rate: u32, format: Format) -> Result<Self> {
+        let align = Self::CHANNELS * format.bytes();
+        let tag: u16 = match format {
+            Format::Int16 | Format::Int24 => 1,
+            Format::Float32 => 3,
+        };
+
+        out.write_all(b"RIFF")?;
+        out.write_all(&0u32.to_le_bytes())?;
+        out.write_all(b"WAVE")?;
+
+        out.write_all(b"fmt ")?;
+        out.write_all(&16u32.to_le_bytes())?;
+        out.write_all(&tag.to_le_bytes())?;
+        out.write_all(&Self::CHANNELS.to_le_bytes())?;
+        out.write_all(&rate.to_le_bytes())?;
+        out.write_all(&(rate * align as u32).to_le_bytes())?;
+        out.write_all(&align.to_le_bytes())?;
+        out.write_all(&(format.bytes() * 8).to_le_bytes())?;
+
+        out.write_all(b"data")?;
+        out.write_all(&0u32.to_le_bytes())?;
+
+        Ok(Self { out, format, frames: 0 })
+    }
+
+    pub fn write(&mut self, l: f32, r: f32) -> Result<()> {
+        for s in [l, r] {
+            match self.format {
+                Format::Int16 => {
+                    let v = (s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+                    self.out.write_all(&v.to_le_bytes())?;
+                }
+                Format::Int24 => {
+                    let v = (s.clamp(-1.0, 1.0) * 8_388_607.0) as i32;
+                    self.out.write_all(&v.to_le_bytes()[..3])?;
+                }
+                Format::Float32 => self.out.write_all(&s.to_le_bytes())?,
+            }
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<W> {
+        let data = self.frames * (Self::CHANNELS * self.format.bytes()) as u32;
+
+        self.out.seek(SeekFrom::Start(4))?;
+        self.out.write_all(&(36 + data).to_le_bytes())?;
+        self.out.seek(SeekFrom::Start(40))?;
+        self.out.write_all(&data.to_le_bytes())?;
+        self.out.seek(SeekFrom::End(0))?;
+        self.out.flush()?;
+
+        Ok(self.out)
+    }
+}
+
+/// Reads a WAV file as written by `Writer` (or any 16/24 bit PCM or 32 bit float file),
+/// returning the sample rate and the left and right channels.
+pub fn read(path: impl AsRef<Path>) -> Result<(u32, Vec<f32>, Vec<f32>)> {
+    decode(&std::fs::read(path)?)
+}
+
+fn decode(data: &[u8]) -> Result<(u32, Vec<f32>, Vec<f32>)> {
+    if data.len() < 12 || &data[..4] != b"RIFF" || &data[8..12] != b"WAVE" {
+        bail!("not a WAV file");
+    }
+
+    let u16_at = |i: usize| u16::from_le_bytes([data[i], data[i + 1]]);
+    let u32_at = |i: usize| u32::from_le_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]);
+
+    let mut fmt = None;
+    let mut pos = 12;
+    while pos + 8 <= data.len() {
+        let (id, len) = (&data[pos..pos + 4], u32_at(pos + 4) as usize);
+        let body = pos + 8;
+        match id {
+            b"fmt " if len >= 16 => {
+                if body + 16 > data.len() {
+                    bail!("truncated fmt chunk");
+                }
+                fmt = Some((u16_at(body), u16_at(body + 2), u32_at(body + 4), u16_at(body + 14)));
+            },
+            b"data" => {
+                let Some((tag, channels, rate, bits)) = fmt else { bail!("data before fmt chunk") };
+                let format = match (tag, bits) {
+                    (1, 16) => Format::Int16,
+                    (1, 24) => Format::Int24,
+                    (3, 32) => Format::Float32,
+                    _ => bail!("unsupported sample format: tag {tag}, {bits} bits"),
+                };
+
+                let width = format.bytes() as usize;
+                let samples = data[body..(body + len).min(data.len())].chunks_exact(width).map(|s| match format {
+                    Format::Int16 => i16::from_le_bytes([s[0], s[1]]) as f32 / i16::MAX as f32,
+                    Format::Int24 => (i32::from_le_bytes([0, s[0], s[1], s[2]]) >> 8) as f32 / 8_388_607.0,
+                    Format::Float32 => f32::from_le_bytes([s[0], s[1], s[2], s[3]]),
+                }).collect::<Vec<_>>();
+
+                let channels = channels.max(1) as usize;
+                let frames = samples.chunks_exact(channels);
+                let left = frames.clone().map(|f| f[0]).collect();
+                let right = frames.map(|f| f[channels.min(2) - 1]).collect();
+                return Ok((rate, left, right));
+            },
+            _ => {},
+        }
+        // chunks are padded to an even length
+        pos = body + len + (len & 1);
+    }
+    bail!("no data chunk")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    const SAMPLES: [f32; 6] = [0.0, 0.5, -0.5, 1.0, -1.0, 0.123];
+
+    fn write(format: Format) -> Vec<u8> {
+        let mut wav = Writer::new(Cursor::new(vec![]), 44_100, format).unwrap();
+        for s in SAMPLES {
+            wav.write(s, -s).unwrap();
+        }
+        wav.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn roundtrip() {
+        for (format, step) in [(Format::Int16, 1.0 / 32_767.0), (Format::Int24, 1.0 / 8_388_607.0), (Format::Float32, 0.0)] {
+            let (rate, left, right) = decode(&write(format)).unwrap();
+            assert_eq!(rate, 44_100);
+            assert_eq!(left.len(), SAMPLES.len(), "{format:?}");
+            for ((s, l), r) in SAMPLES.iter().zip(left).zip(right) {
+                assert!((l - s).abs() <= step && (r + s).abs() <= step, "{format:?}: {s} came back as {l}, {r}");
+            }
+        }
+    }
+
+    #[test]
+    fn truncated() {
+        let wav = write(Format::Int16);
+        // cut inside the fmt chunk
+        for len in [24, 30] {
+            assert!(decode(&wav[..len]).is_err());
+        }
+        // cut inside the data, which loses the incomplete frame
+        let (_, left, _) = decode(&wav[..wav.len() - 3]).unwrap();
+        assert_eq!(left.len(), SAMPLES.len() - 1);
+    }
+}